@@ -0,0 +1,118 @@
+/*
+* undo benchmark - run with `cargo bench --bench undo`
+*
+* types into the middle of a multi-MB file the way buffer.rs drives the
+* history (a stash every word and every cursor move, arrow wiggles included),
+* then undoes and redoes everything. the snapshot numbers are the old approach, a rope clone plus a
+* full comparison per stash, kept here to compare against. the bench fails
+* if the changes ever get slower than the snapshots.
+*/
+#[path = "../src/history.rs"]
+#[allow(dead_code)]
+mod history;
+
+use history::{Change, History};
+use std::time::{Duration, Instant};
+
+const LINES: usize = 100_000;
+const KEYS: usize = 5_000;
+
+fn big_file() -> ropey::Rope {
+	let line = "the quick brown fox jumps over the lazy dog, again and again\n";
+	ropey::Rope::from(line.repeat(LINES))
+}
+
+/// the text typed, a word every 6 keys
+fn key(i: usize) -> char {
+	if i % 6 == 5 { ' ' } else { (b'a' + (i % 26) as u8) as char }
+}
+
+/// where the cursor jumps to every now and then
+fn jump(i: usize, len: usize) -> usize {
+	(i * 7919) % len
+}
+
+fn bench_delta(text: &ropey::Rope) -> (Duration, Duration, Duration, usize) {
+	let mut rope = text.clone();
+	let mut h = History::default();
+	let mut cs = rope.len_chars() / 2;
+
+	let start = Instant::now();
+	for i in 0..KEYS {
+		if i % 100 == 99 {
+			h.stash();
+			cs = jump(i, rope.len_chars());
+		}
+		// arrow left and back, nothing changes
+		if i % 10 == 9 { h.stash(); h.stash(); }
+		let c = key(i);
+		if c.is_whitespace() { h.stash(); }
+		rope.insert_char(cs, c);
+		h.record(Change::insert(cs, c), cs, cs + 1);
+		cs += 1;
+	}
+	h.stash();
+	let typing = start.elapsed();
+
+	let start = Instant::now();
+	let mut steps = 0;
+	while h.undo(&mut rope).is_some() { steps += 1; }
+	let undo = start.elapsed();
+	assert_eq!(text, &rope);
+
+	let start = Instant::now();
+	while h.redo(&mut rope).is_some() {}
+	let redo = start.elapsed();
+	(typing, undo, redo, steps)
+}
+
+fn bench_snapshot(text: &ropey::Rope) -> (Duration, Duration, usize) {
+	let mut rope = text.clone();
+	let mut timeline = vec![rope.clone()];
+	let mut cs = rope.len_chars() / 2;
+	let stash = |timeline: &mut Vec<ropey::Rope>, rope: &ropey::Rope| {
+		if !rope.eq(timeline.last().unwrap()) {
+			timeline.push(rope.clone());
+		}
+	};
+
+	let start = Instant::now();
+	for i in 0..KEYS {
+		if i % 100 == 99 {
+			stash(&mut timeline, &rope);
+			cs = jump(i, rope.len_chars());
+		}
+		if i % 10 == 9 { stash(&mut timeline, &rope); stash(&mut timeline, &rope); }
+		let c = key(i);
+		if c.is_whitespace() { stash(&mut timeline, &rope); }
+		rope.insert_char(cs, c);
+		cs += 1;
+	}
+	stash(&mut timeline, &rope);
+	let typing = start.elapsed();
+
+	let start = Instant::now();
+	let steps = timeline.len() - 1;
+	while timeline.len() > 1 {
+		timeline.pop();
+		rope = timeline.last().unwrap().clone();
+	}
+	let undo = start.elapsed();
+	assert_eq!(text, &rope);
+	(typing, undo, steps)
+}
+
+fn main() {
+	let text = big_file();
+	println!("file: {:.1} MB, {} keystrokes", text.len_bytes() as f64 / 1e6, KEYS);
+
+	let (typing, undo, redo, steps) = bench_delta(&text);
+	println!("delta:    typing {:>10.2?}  undo {:>10.2?}  redo {:>10.2?}  ({} steps)", typing, undo, redo, steps);
+
+	let (snap_typing, snap_undo, snap_steps) = bench_snapshot(&text);
+	println!("snapshot: typing {:>10.2?}  undo {:>10.2?}  ({} steps)", snap_typing, snap_undo, snap_steps);
+
+	assert_eq!(snap_steps, steps, "both should undo the same edits");
+	assert!(typing < snap_typing, "typing with changes is slower than with snapshots");
+	assert!(typing + undo < snap_typing + snap_undo, "changes are slower than snapshots overall");
+}
@@ -1,8 +1,19 @@
 /*
 * buffer struct - this stores the file info & content
 */
-use crate::history::History;
+use crate::file::{self, Format, Stamp, WriteError, WriteOpts};
+use crate::hex;
+use crate::history::{self, Change, Cursor, EditAction, Group, History, Landing, Span};
+use crate::options::BufOptions;
 use crate::selection::Selection;
+use crate::swap;
+use crate::syntax::{self, Highlighter};
+use crate::tree::Object;
+use crate::width;
+use std::{io, ops::Range, path::Path, time::{Duration, Instant}};
+
+/// how long the text changed by undo/redo stays highlighted
+const FLASH: Duration = Duration::from_millis(400);
 
 pub struct Buffer {
     pub lines: ropey::Rope,
@@ -12,10 +23,35 @@ pub struct Buffer {
     cached_cx: usize,
     // undo stuff
     history: History,
+	// bumped on every change to lines
+	version: usize,
+	// bumped when options change how the text is laid out
+	relaid: usize,
+	// version last written to the swap file
+	swapped: usize,
+	pub options: BufOptions,
+	// the file on disk, as of the last read/write
+	pub stamp: Option<Stamp>,
+	// version of the file on disk the user was already warned about
+	pub warned: Option<Stamp>,
+	// readonly was set by opening the file readonly at the swap question
+	pub readonly_for_swap: bool,
+	// format of the file on disk, a different options.format means unsaved changes
+	saved_format: Format,
+	// lines hold a hex dump of the file (see hex.rs)
+	pub hex: bool,
 	// visual stuff 
 	pub visual : Vec<VisualLine>,
     pub viewport : ViewPort,
 	pub selection: Selection,
+	// text changed by the last undo/redo, and when
+	flash: Option<(Range<usize>, Instant)>,
+	// bytes of the last hex search, and the version of the text they were found in
+	found: Option<(Range<usize>, usize)>,
+	// None for files no language knows about
+	pub syntax: Option<Highlighter>,
+	// edits the views of other windows havent followed yet, see take_shifts
+	shifts: Vec<Shift>,
 }
 
 impl Buffer {
@@ -37,23 +73,66 @@ impl Buffer {
 			filename,
 			cs: 0,
 			cached_cx : 0,
-			history: History::from(ctx),
+			history: History::default(),
+			version: 0,
+			relaid: 0,
+			swapped: 0,
+			options: BufOptions::default(),
+			stamp: Option::None,
+			warned: Option::None,
+			readonly_for_swap: false,
+			saved_format: Format::default(),
+			hex: false,
 			visual : vec![VisualLine::default()],
             viewport : ViewPort::new(w, h),
-			selection: Selection::default()
+			selection: Selection::default(),
+			flash: Option::None,
+			found: Option::None,
+			syntax: Option::None,
+			shifts: vec![],
         };
         buf.build_visual_line();
+		buf.detect_syntax();
 
         buf
     }
 
+	/// opens a buffer with a file read from disk.
+	/// binary files are opened readonly.
+	pub fn from_file(filename: String, file: file::Loaded, w: usize, h: usize) -> Buffer {
+		let mut buf = Buffer::open(filename, file.text, w, h);
+		buf.options.readonly = file.binary;
+		buf.stamp = Some(file.stamp);
+		buf.options.format = file.format;
+		buf.saved_format = file.format;
+		buf
+	}
+
+	/// opens a hex view of the bytes of a file
+	pub fn from_bytes(filename: String, bytes: &[u8], stamp: Stamp, w: usize, h: usize) -> Buffer {
+		let mut buf = Buffer::open(filename, hex::dump(bytes), w, h);
+		buf.stamp = Some(stamp);
+		buf.syntax = Option::None;
+		buf.hex = true;
+		// the swap of the file is for its text, a dump doesnt belong there
+		buf.options.swapfile = false;
+		buf
+	}
+
 	/// inserts a single char in the buffer
     pub fn insert(&mut self, char: char) {
+		if self.hex { return self.hex_overwrite(char); }
+		let before = self.cursor();
 		self.selection_end();
 		//
-        self.history.update(&char, &self.lines, self.cs);
+        self.history.update(&char, self.options.undogroup);
         // inserting
+		self.syntax_edit(self.cs, 0, char.encode_utf8(&mut [0; 4]));
         self.lines.insert_char(self.cs, char);
+		self.shifts.push(Shift { at: self.cs, removed: 0, inserted: 1 });
+		let after = Cursor { cs: self.cs + 1, ..self.cursor() };
+		self.history.record(Change::insert(self.cs, char), before, after);
+		self.version += 1;
 		// visual lines
         self.update_visual_line(Some(char));
         // 
@@ -61,39 +140,241 @@ impl Buffer {
 		_ = self.viewport_fix_offset(Option::None);
     }
 
-	/// deletes amt chars
-    pub fn delete(&mut self, mut amt: usize, backspace: bool) {
+	/// tab key, up to the next stop with expandtab or softtabstop
+	pub fn insert_tab(&mut self) {
+		let soft = self.options.expandtab || self.options.softtabstop > 0;
+		if self.hex || !soft { return self.insert('\t'); }
+		let stop = self.options.tab_columns();
+		let target = (self.line_col() / stop + 1) * stop;
+		// one tab press, one undo step
+		self.history.begin_group();
+		self.fill_to(target);
+		self.history.end_group();
+	}
+
+	/// backspace key, over blanks it goes back to the previous stop
+	pub fn backspace(&mut self) {
+		let (soft, et) = (self.options.expandtab || self.options.softtabstop > 0, self.options.expandtab);
+		// without expandtab tabs are part of the room too
+		let blank = move |c: char| c == ' ' || (c == '\t' && !et);
+		let prev = self.cs.checked_sub(1).map(|i| self.lines.char(i));
+		if !soft || !prev.is_some_and(blank) { return self.delete(1, true); }
+
+		let (col, stop) = (self.line_col(), self.options.tab_columns());
+		let target = col.saturating_sub(1) / stop * stop;
+		self.history.begin_group();
+		while self.line_col() > target && self.cs > 0 && blank(self.lines.char(self.cs - 1)) {
+			self.delete(1, true);
+		}
+		// a tab can take it past the stop, the rest comes back
+		self.fill_to(target);
+		self.history.end_group();
+	}
+
+	/// fills the line from the cursor up to column target. spaces with expandtab,
+	/// otherwise tabs where they fit and spaces after, the spaces just before
+	/// the cursor turn into tabs too (like vim does)
+	fn fill_to(&mut self, target: usize) {
+		let col = self.line_col();
+		if col >= target { return; }
+		let ts = self.options.tabstop;
+		let spaces = self.lines.chars_at(self.cs).reversed().take_while(|&c| c == ' ').count();
+		let mut at = col - spaces.min(col);
+		if self.options.expandtab || at / ts * ts + ts > target {
+			at = col;
+		} else if spaces > 0 {
+			self.delete(spaces, true);
+		}
+		while !self.options.expandtab && at / ts * ts + ts <= target {
+			self.insert('\t');
+			at = at / ts * ts + ts;
+		}
+		for _ in at..target {
+			self.insert(' ');
+		}
+	}
+
+	/// rewrites the indentation of every line for the current expandtab,
+	/// tabs in it are read with the old tabstop and the new one is set after
+	pub fn retab(&mut self, tabstop: Option<usize>) {
+		let (old, new) = (self.options.tabstop, tabstop.unwrap_or(self.options.tabstop));
+		let cursor_line = self.lines.char_to_line(self.cs);
+		let mut cs = self.cs;
+		let mut text = String::with_capacity(self.lines.len_bytes());
+		for (i, line) in self.lines.lines().enumerate() {
+			let indent = line.chars().take_while(|c| matches!(c, ' ' | '\t')).count();
+			let cols = width::str_width(line.slice(..indent), 0, old);
+			let new_indent = match self.options.expandtab {
+				true => " ".repeat(cols),
+				false => "\t".repeat(cols / new) + &" ".repeat(cols % new),
+			};
+			// the cursor keeps its place in the text after the indentation
+			if i == cursor_line {
+				let col = self.cs - self.lines.line_to_char(i);
+				let line_start = text.chars().count();
+				cs = line_start + new_indent.chars().count() + col.saturating_sub(indent);
+			}
+			text.push_str(&new_indent);
+			text.extend(line.chars().skip(indent));
+		}
+		self.options.tabstop = new;
+		match self.lines == text.as_str() {
+			true => self.refresh(),
+			false => self.set_text(ropey::Rope::from(text), cs),
+		}
+	}
+
+	/// column of the cursor inside its line, on screen
+	fn line_col(&self) -> usize {
+		let start = self.lines.line_to_char(self.lines.char_to_line(self.cs));
+		width::str_width(self.lines.slice(start..self.cs), 0, self.options.tabstop)
+	}
+
+	/// deletes amt graphemes
+    pub fn delete(&mut self, amt: usize, backspace: bool) {
+		// the hex view only overwrites
+		if self.hex { return; }
+		let before = self.cursor();
 		self.selection_end();
-        // bounds check
-		if backspace {
-			amt = self.cs.min(amt);
-			if amt == 0 { return; }
-			// clever trick to simplify deleting chars: mv cursor first
-			self.cursor_mv_exact(Direction::Horiz, -(amt as i32));
-		} else {
-			amt = amt.min(self.lines.len_chars() - self.cs);
-			if amt == 0 { return; }
+        // bounds check, amt counts graphemes
+		let (mut from, mut to) = (self.cs, self.cs);
+		for _ in 0..amt {
+			match backspace {
+				true => from = width::prev_grapheme(&self.lines, from),
+				false => to = width::next_grapheme(&self.lines, to),
+			}
 		}
+		if from == to { return; }
+		// clever trick to simplify deleting chars: mv cursor first
+		self.cs = from;
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+		let amt = to - from;
+		let removed = self.lines.slice(self.cs .. self.cs + amt).to_string();
+		// deleting a space ends a word like typing one does
+		let first = if backspace { removed.chars().last() } else { removed.chars().next() };
+		self.history.update(&first.unwrap_or(' '), self.options.undogroup);
+		self.syntax_edit(self.cs, amt, "");
         self.lines.remove(self.cs .. self.cs + amt);
+		self.shifts.push(Shift { at: self.cs, removed: amt, inserted: 0 });
+		self.history.record(Change::remove(self.cs, removed), before, self.cursor());
+		self.version += 1;
 		// visual line stuff
-        if amt == 1 { self.update_visual_line(Option::None); }
+        if amt == 1 && narrow(first) { self.update_visual_line(Option::None); }
         else { self.build_visual_line(); }
         
 		_ = self.viewport_fix_offset(Option::None);
-        self.history.update(&true, &self.lines, self.cs); 
     }
 
-	/// fixes modified status and history
-	pub fn save(&mut self) {
+	/// writes the buffer to path and, once it's safely on disk,
+	/// takes path as its new name and marks the history as saved.
+	/// on error the buffer is left untouched (and still modified).
+	pub fn write_to(&mut self, path: impl AsRef<Path>, opts: WriteOpts) -> Result<(), WriteError> {
+		let path = path.as_ref();
+		if path.as_os_str().is_empty() { return Err(WriteError::NoFilename); }
+		if self.options.readonly { return Err(WriteError::ReadOnly); }
+		if !opts.force && path == Path::new(&self.filename) && self.disk_changed() {
+			return Err(WriteError::Changed);
+		}
+
+		let format = self.options.format;
+		let stamp = if self.hex {
+			let bytes = hex::parse(&self.lines);
+			file::write_stamped(path, opts.backup, |wr| wr.write_all(&bytes))?
+		} else {
+			file::write_stamped(path, opts.backup, |wr| file::encode(wr, &self.lines, format))?
+		};
+
+		// everything is on disk, the swap is not needed anymore
+		self.remove_swap();
+		let renamed = path != Path::new(&self.filename);
+		self.filename = path.to_string_lossy().into_owned();
+		// saved as foo.rs, now it's rust
+		if renamed && !self.hex { self.detect_syntax(); }
+		self.stamp = Some(stamp);
+		self.saved_format = format;
+		self.history.save();
+		Ok(())
+	}
+
+	/// true if the file was changed by someone else since we last read/wrote it
+	pub fn disk_changed(&self) -> bool {
+		self.stamp.is_some_and(|s| s.changed(Path::new(&self.filename)))
+	}
+
+	/// reads the file again, throwing away any change.
+	/// the reload itself can be undone.
+	pub fn reload(&mut self, fallback: &'static encoding_rs::Encoding) -> io::Result<()> {
+		if self.hex {
+			let bytes = std::fs::read(&self.filename)?;
+			self.set_text(hex::dump(&bytes), self.cs);
+			self.stamp = Some(Stamp::read(Path::new(&self.filename))?);
+			self.history.save();
+			return Ok(());
+		}
+		let file = file::read(Path::new(&self.filename), fallback)?;
+		self.set_text(file.text, self.cs);
+		self.stamp = Some(file.stamp);
+		self.options.format = file.format;
+		self.saved_format = file.format;
 		self.history.save();
+		Ok(())
+	}
+
+	/// replaces the whole text, as a single undoable edit.
+	/// only the part that differs counts as changed
+	pub fn set_text(&mut self, text: ropey::Rope, cs: usize) {
+		let before = self.cursor();
+		self.selection_end();
+		self.history.stash();
+
+		let change = Change::between(&self.lines.to_string(), &text.to_string());
+		history::Text::replace(
+			&mut Tracked { lines: &mut self.lines, syntax: self.syntax.as_mut(), shifts: &mut self.shifts },
+			change.at, &change.removed, &change.inserted
+		);
+		self.cs = cs.min(self.lines.len_chars());
+		self.version += 1;
+
+		// the same text again is no edit
+		if !change.removed.is_empty() || !change.inserted.is_empty() {
+			self.history.record(change, before, self.cursor());
+		}
+		self.history.stash();
+		self.build_visual_line();
+		_ = self.viewport_fix_offset(Option::None);
+	}
+
+	/// true if this buffer keeps a swap file. buffers with no file on disk
+	/// dont, they would all share the swap of `new-file.md`
+	fn keeps_swap(&self) -> bool {
+		self.options.swapfile && self.stamp.is_some()
+	}
+
+	/// writes the swap file if there are changes it doesnt have yet
+	pub fn sync_swap(&mut self) -> io::Result<()> {
+		if !self.keeps_swap() || !self.is_modified() || self.swapped == self.version {
+			return Ok(());
+		}
+		swap::write(&self.filename, self.cs, &self.lines)?;
+		self.swapped = self.version;
+		Ok(())
+	}
+
+	/// deletes the swap file of this buffer, if it owns one
+	pub fn remove_swap(&mut self) {
+		if !self.keeps_swap() { return; }
+		_ = swap::remove(&self.filename);
+		self.swapped = self.version;
 	}
 
 	/// entry point to move the cursor
 	/// if called, stashes history, if you dont want this, call the mv_exact or mv_word
     /// also, only this fn updates the viewport
 	pub fn cursor_mv(&mut self, mv: Move) {
-        self.history.update(&mv, &self.lines, self.cs);
+        self.history.update(&mv, self.options.undogroup);
 		match mv {
+			Move::Exact(Direction::Horiz, amt) if self.hex => self.hex_mv(amt),
+			Move::Word(amt) if self.hex => self.hex_mv(amt * 2),
 			Move::Exact(dir, amt) => self.cursor_mv_exact(dir, amt),
 			Move::Word(amt) => self.cursor_mv_word(amt)
 		}
@@ -102,6 +383,55 @@ impl Buffer {
 		self.selection_check_update();
 	}
 
+	/// moves the cursor amt nibbles in the hex view
+	fn hex_mv(&mut self, amt: i32) {
+		let nib = hex::char_to_nibble(&self.lines, self.cs) as i32 + amt;
+		let last = hex::len(&self.lines) as i32 - 1;
+		self.cs = hex::nibble_to_char(&self.lines, nib.clamp(0, last.max(0)) as usize);
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+	}
+
+	/// sets the nibble under the cursor to a hex digit and moves on to the next one.
+	/// anything that isnt a hex digit is ignored.
+	fn hex_overwrite(&mut self, digit: char) {
+		let nib = hex::char_to_nibble(&self.lines, self.cs);
+		let Some(edits) = hex::overwrite(&self.lines, nib, digit) else { return; };
+		let before = self.cursor();
+		self.selection_end();
+		self.history.update(&digit, self.options.undogroup);
+		let cs_after = nibble_to_char_clamped(&self.lines, nib + 1);
+		let after = Cursor { cs: cs_after, ..self.cursor() };
+		for (at, c) in edits {
+			let old = self.lines.char(at);
+			self.lines.remove(at..at + 1);
+			self.lines.insert_char(at, c);
+			self.shifts.push(Shift { at, removed: 1, inserted: 1 });
+			self.history.record(Change { at, removed: old.into(), inserted: c.into() }, before, after);
+		}
+		self.version += 1;
+		// the dump keeps its shape, visual lines are still good
+		self.cs = cs_after;
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+		_ = self.viewport_fix_offset(Option::None);
+	}
+
+	/// moves the cursor to the next match of a byte pattern, wrapping around.
+	/// returns false if there's none.
+	pub fn hex_find(&mut self, needle: &[u8]) -> bool {
+		let bytes = hex::parse(&self.lines);
+		let from = hex::char_to_nibble(&self.lines, self.cs) / 2;
+		let Some(at) = hex::find(&bytes, needle, from) else {
+			self.found = Option::None;
+			return false;
+		};
+		self.found = Some((at..at + needle.len(), self.version));
+		self.history.update(&Move::Exact(Direction::Horiz, 0), self.options.undogroup);
+		self.cs = hex::nibble_to_char(&self.lines, at * 2);
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+		_ = self.viewport_fix_offset(Option::None);
+		true
+	}
+
 	/// this moves the cursor an amt amount of words
 	fn cursor_mv_word(&mut self, mut amt: i32) {
 		let inc = amt.signum();
@@ -136,12 +466,18 @@ impl Buffer {
 				);
             },
             Direction::Horiz => {
-                // check bounds
-                if self.cs as i32 + amt < 0
-                    || self.cs as i32 + amt > self.lines.len_chars() as i32 {
-                    return;
-                } 
-                self.cs = (amt + self.cs as i32) as usize;
+                // moves by grapheme, an accent or emoji is a single step
+                let mut cs = self.cs;
+                for _ in 0..amt.unsigned_abs() {
+                    let next = match amt < 0 {
+                        true => width::prev_grapheme(&self.lines, cs),
+                        false => width::next_grapheme(&self.lines, cs),
+                    };
+                    // check bounds
+                    if next == cs { return; }
+                    cs = next;
+                }
+                self.cs = cs;
                 // update the cached cx
                 self.cached_cx = self.get_cursor_pos().0 as usize;
             },
@@ -153,6 +489,7 @@ impl Buffer {
 		let tmp = self.lines.char_to_line(self.cs);
 		self.cs = self.lines.line_to_char(tmp);
 		self.cached_cx = 0;
+		_ = self.viewport_fix_offset(Option::None);
 
 		self.selection_check_update();
 	}
@@ -166,10 +503,24 @@ impl Buffer {
 			self.lines.len_chars()
 		};
 		self.cached_cx = self.get_cursor_pos().0 as usize;
+		_ = self.viewport_fix_offset(Option::None);
 
 		self.selection_check_update();
 	}
 
+	/// width of the gutter left of the text: line numbers and a space,
+	/// hex views show the offset of each row instead
+	pub fn gutter_width(&self) -> usize {
+		if self.hex { return 9; }
+		if !self.options.number && !self.options.relativenumber { return 0; }
+		self.lines.len_lines().to_string().len().max(3) + 1
+	}
+
+	/// the cursor, as a rope index
+	pub fn cs(&self) -> usize {
+		self.cs
+	}
+
     /// wrapper method to get the cursor (cx, cy) coords
     /// 
     /// **NOTE**: cy is the *relative* position, meaning it takes
@@ -186,30 +537,204 @@ impl Buffer {
 	/// cursor is put back in the previews place.
     pub fn undo(&mut self) {
 		self.selection_end();
+        let cs = self.history.undo(&mut Tracked { lines: &mut self.lines, syntax: self.syntax.as_mut(), shifts: &mut self.shifts });
+		self.history_moved(cs);
+    }
+
+	/// redoes an edit, on the branch that was last undone.
+    pub fn redo(&mut self) {
+		self.selection_end();
+        let cs = self.history.redo(&mut Tracked { lines: &mut self.lines, syntax: self.syntax.as_mut(), shifts: &mut self.shifts });
+		self.history_moved(cs);
+    }
+
+	/// brings the text to state seq of the history, on any branch
+	pub fn undo_goto(&mut self, seq: usize) {
+		self.selection_end();
+		let cs = self.history.goto(seq, &mut Tracked { lines: &mut self.lines, syntax: self.syntax.as_mut(), shifts: &mut self.shifts });
+		self.history_moved(cs);
+	}
 
-        self.history.stash(&self.lines, self.cs);
+	/// `:earlier`/`:later`, returns false if there was nowhere to go
+	pub fn undo_travel(&mut self, back: bool, span: Span) -> bool {
+		let seq = match back {
+			true => self.history.earlier(span),
+			false => self.history.later(span),
+		};
+		if seq == self.history.seq() { return false; }
+		self.undo_goto(seq);
+		true
+	}
 
-        let edit = self.history.undo();
-        self.lines = edit.text.clone();
-        self.cs = edit.cs;
+	/// everything until end_group is undone in one step
+	pub fn begin_group(&mut self) {
+		self.history.begin_group();
+	}
 
-        self.build_visual_line();
+	pub fn end_group(&mut self) {
+		self.history.end_group();
+	}
+
+	/// leaving insert mode, ends the undo step with the insert policy
+	pub fn end_insert(&mut self) {
+		if self.options.undogroup == Group::Insert {
+			self.history.stash();
+		}
+	}
+
+	/// takes over a history read from an undo file,
+	/// its saved state has to be the text of the buffer
+	pub fn restore_history(&mut self, history: History) {
+		self.history = history;
+	}
+
+	pub fn history(&self) -> &History {
+		&self.history
+	}
+
+	/// the text was changed by the history: puts cursor, selection and
+	/// viewport back where they were, and highlights what changed
+	fn history_moved(&mut self, landing: Option<Landing>) {
+		let Some(Landing { cursor, changed }) = landing else { return; };
+		let len = self.lines.len_chars();
+		self.cs = cursor.cs.min(len);
+		self.version += 1;
+
+		self.build_visual_line();
+		self.viewport.offset = cursor.offset.min(self.visual.len() - 1);
 		_ = self.viewport_fix_offset(Option::None);
-    }
+		if let Some((anchor, end)) = cursor.selection {
+			self.selection.active = true;
+			self.selection.anchor = anchor.min(len);
+			self.selection.end = end.min(len);
+		}
+		self.flash = Some((changed.start.min(len)..changed.end.min(len), Instant::now()));
+	}
 
-	/// redoes an edit.
-	/// possible only if undo command was just executed.
-    pub fn redo(&mut self) {
+	/// picks the highlighting for the file name, if there is one
+	pub fn detect_syntax(&mut self) {
+		self.syntax = syntax::for_file(&self.filename).map(Highlighter::new);
+	}
+
+	/// the text is about to change at cs, keeps the highlighting in step
+	fn syntax_edit(&mut self, cs: usize, removed: usize, inserted: &str) {
+		if let Some(syntax) = &mut self.syntax {
+			syntax.edit(&self.lines, cs, removed, inserted);
+		}
+	}
+
+	/// `]f`/`[f`, moves to the next (or previous) function.
+	/// false if there's none, or no syntax tree.
+	pub fn function_jump(&mut self, forward: bool) -> bool {
+		let Some(tree) = self.syntax.as_mut().and_then(|s| s.tree(&self.lines)) else { return false; };
+		let Some(cs) = tree.function_jump(&self.lines, self.cs, forward) else { return false; };
+		self.history.update(&Move::Exact(Direction::Horiz, 0), self.options.undogroup);
+		self.cs = cs;
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+		_ = self.viewport_fix_offset(Option::None);
+		self.selection_check_update();
+		true
+	}
+
+	/// selects the function, class or argument around the cursor (or selection)
+	pub fn select_object(&mut self, obj: Object) -> bool {
+		let range = self.selection_or_cursor();
+		let Some(tree) = self.syntax.as_mut().and_then(|s| s.tree(&self.lines)) else { return false; };
+		let Some(found) = tree.object(&self.lines, range, obj) else { return false; };
+		self.select(found);
+		true
+	}
+
+	/// grows the selection to the syntax node around it
+	pub fn expand_selection(&mut self) -> bool {
+		let range = self.selection_or_cursor();
+		let Some(tree) = self.syntax.as_mut().and_then(|s| s.tree(&self.lines)) else { return false; };
+		let Some(found) = tree.expand(&self.lines, range.clone()) else { return false; };
+		let expanded = std::mem::take(&mut self.selection.expanded);
+		self.select(found);
+		self.selection.expanded = expanded;
+		self.selection.expanded.push(range);
+		true
+	}
+
+	/// undoes an expand_selection, false if there was none
+	pub fn shrink_selection(&mut self) -> bool {
+		let Some(range) = self.selection.expanded.pop() else { return false; };
+		let expanded = std::mem::take(&mut self.selection.expanded);
+		match range.is_empty() {
+			true => {
+				self.selection_end();
+				self.cs = range.start;
+				self.cached_cx = self.get_cursor_pos().0 as usize;
+				_ = self.viewport_fix_offset(Option::None);
+			},
+			false => self.select(range),
+		}
+		self.selection.expanded = expanded;
+		true
+	}
+
+	fn selection_or_cursor(&self) -> Range<usize> {
+		match self.selection.active {
+			true => self.selection.range_raw(),
+			false => self.cs..self.cs,
+		}
+	}
+
+	/// selects range, with the cursor at its end
+	fn select(&mut self, range: Range<usize>) {
+		self.history.update(&Move::Exact(Direction::Horiz, 0), self.options.undogroup);
 		self.selection_end();
+		self.selection.active = true;
+		self.selection.anchor = range.start;
+		self.selection.end = range.end;
+		self.cs = range.end;
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+		_ = self.viewport_fix_offset(Option::None);
+	}
 
-        if let Some(edit) = self.history.redo() {
-            self.lines = edit.text.clone();
-            self.cs = edit.cs;
+	/// highlights what is on screen, if it isnt already
+	pub fn update_syntax(&mut self) {
+		let Some(syntax) = &mut self.syntax else { return; };
+		// a terminal shrunk to nothing leaves no rows
+		let last = (self.viewport.offset + self.viewport.height).min(self.visual.len()).saturating_sub(1);
+		let Some(line) = self.visual.get(last) else { return; };
+		syntax.update(&self.lines, line.rope);
+	}
 
-			self.build_visual_line();
-            _ = self.viewport_fix_offset(Option::None);
-        }
-    }
+	/// where the user is, for the history
+	fn cursor(&self) -> Cursor {
+		Cursor {
+			cs: self.cs,
+			selection: self.selection.active.then_some((self.selection.anchor, self.selection.end)),
+			offset: self.viewport.offset,
+		}
+	}
+
+	/// where the bytes of the last hex search are in the dump, until the text changes
+	pub fn found(&self) -> Vec<Range<usize>> {
+		match &self.found {
+			Some((bytes, version)) if *version == self.version => bytes.clone()
+				.map(|b| {
+					let at = hex::nibble_to_char(&self.lines, b * 2);
+					at..at + 2
+				})
+				.collect(),
+			_ => vec![],
+		}
+	}
+
+	/// text changed by the last undo/redo, while it should still be highlighted
+	pub fn flash(&self) -> Option<Range<usize>> {
+		self.flash.as_ref()
+			.filter(|(_, at)| at.elapsed() < FLASH)
+			.map(|(range, _)| range.clone())
+	}
+
+	/// how long until the highlight goes away, so the screen gets redrawn
+	pub fn flash_left(&self) -> Option<Duration> {
+		self.flash.as_ref().and_then(|(_, at)| FLASH.checked_sub(at.elapsed()))
+	}
 
     /// converts between index in the Rope to indexes (col, row).
     /// panics if indexes cant be found.
@@ -243,36 +768,25 @@ impl Buffer {
 		let vl = self.visual[cy];
 		
 		// total offset from the beginning of the rope line
-		// let tot_off = vl.offset + visual_cx;
-		let tab_width = 4;
+		let tab_width = self.options.tabstop;
 		let mut curr_col = 0;
-		let char_cx = self.lines.line(vl.rope)
-			.slice(vl.offset..vl.offset + vl.len)
-			.chars()
-			.take_while(|ch| { 
-				curr_col += if *ch == '\t' {
-					tab_width - (curr_col % tab_width)
-				} else { 1 };
+		let char_cx: usize = width::cells(self.lines.line(vl.rope).slice(vl.offset..vl.offset + vl.len), 0, tab_width)
+			.into_iter()
+			.take_while(|cell| {
+				curr_col += cell.width;
 				curr_col <= visual_cx
 			})
-			.count();
+			.map(|cell| cell.chars)
+			.sum();
 		let tot_off = vl.offset + char_cx;
 
 		tot_off + self.lines.line_to_char(vl.rope)
 	}
 
-	/// returns the visual x coord of the cursor accounting for tabs
+	/// returns the visual x coord of the cursor accounting for tabs and wide chars
 	fn visual_cx(&self, vl: &VisualLine, char_cx: usize) -> usize {
-		let tab_width = 4;
-		self.lines.line(vl.rope)
-			.slice(vl.offset..vl.offset + char_cx)
-			.chars()
-			.fold(0, |mut acc, c| {
-				acc += if c == '\t' {
-					tab_width - (acc % tab_width)
-				} else { 1 };
-				acc
-			})
+		let tab_width = self.options.tabstop;
+		width::str_width(self.lines.line(vl.rope).slice(vl.offset..vl.offset + char_cx), 0, tab_width)
 	}
 
     /// update visual line after the insertion/deletion of a *single* char.
@@ -282,9 +796,9 @@ impl Buffer {
         let (cx, cy) = self.rope_to_visual(self.cs);
         match c {
             Some(c) => {
-                if c == '\n' || c == '\t' {
+                if !narrow(Some(c)) {
                     self.build_visual_line();
-                } else if self.viewport.width >= self.visual[cy].vis_width + 1 {
+                } else if !self.options.wrap || self.viewport.width > self.visual[cy].vis_width {
                     self.visual[cy].len += 1;
                     self.visual[cy].vis_width += 1;
                 } else { self.build_visual_line(); }
@@ -302,7 +816,10 @@ impl Buffer {
     /// completely rebuilds self.visual.
     /// *can* deal with terminal copy/paste correctly
     fn build_visual_line(&mut self) {
-		let tab_width = 4;
+		let tab_width = self.options.tabstop;
+		// without wrap every line is a single row, as wide as it gets
+		let max_width = if self.options.wrap { self.viewport.width } else { usize::MAX };
+		let linebreak = self.options.linebreak;
         self.visual = self.lines.lines()
             .enumerate()
             .flat_map(|(i, line)| {
@@ -310,27 +827,35 @@ impl Buffer {
                 let mut vec = vec![];
                 let mut offset = 0;
 
+				let cells = width::cells(line, 0, tab_width);
+				let mut cells = cells.as_slice();
 				while rope_len > 0 {
-                    let mut vis_width = 0;
-					let mut char_len = line.slice(offset..)
-                        .chars()
-                        .scan(0, |width, ch| {
-                            let w = match ch {
-                                '\t' => tab_width - (vis_width % tab_width),
-                                _ => 1
-                            };
-                            if *width + w > self.viewport.width { return None; }
-
-                            *width += w;
-                            vis_width = *width;
-
-                            Some(())
-                        })
-                        .take(rope_len)
-                        .count();
-                                        
-					// edge case: a single char exceed width
-					if char_len == 0 { char_len = 1; }
+					// as many graphemes as fit, tab stops count from the start of the row
+					let (mut vis_width, mut char_len, mut n) = (0, 0, 0);
+					// with linebreak, the row ends after the last blank that fit
+					let mut blank = Option::None;
+					for cell in cells {
+						let w = cell.width_at(vis_width, tab_width);
+						if vis_width + w > max_width {
+							if let Some(fit) = blank.filter(|_| linebreak) {
+								(vis_width, char_len, n) = fit;
+							}
+							break;
+						}
+						vis_width += w;
+						char_len += cell.chars;
+						n += 1;
+						if cell.blank {
+							blank = Some((vis_width, char_len, n));
+						}
+					}
+					// edge case: a single grapheme exceeds width
+					if n == 0 {
+						n = 1;
+						char_len = cells[0].chars;
+						vis_width = cells[0].width_at(0, tab_width);
+					}
+					cells = &cells[n..];
 
 					vec.push(VisualLine { offset, len: char_len, rope: i, vis_width });
 
@@ -346,6 +871,54 @@ impl Buffer {
             .collect();
     }
 
+	/// lays the text out again, after options that change how it looks
+	pub fn refresh(&mut self) {
+		self.relaid += 1;
+		self.build_visual_line();
+		self.cached_cx = self.get_cursor_pos().0 as usize;
+		_ = self.viewport_fix_offset(Option::None);
+	}
+
+	/// a copy of where the buffer is looked at from
+	pub fn view(&self) -> View {
+		View {
+			cs: self.cs,
+			cached_cx: self.cached_cx,
+			visual: self.visual.clone(),
+			viewport: self.viewport,
+			selection: self.selection.clone(),
+			laid_out: (self.version, self.relaid),
+		}
+	}
+
+	/// the edits made since the last call, for the views of other windows to follow
+	pub fn take_shifts(&mut self) -> Vec<Shift> {
+		std::mem::take(&mut self.shifts)
+	}
+
+	/// looks at the buffer from view, the one it had ends up in view.
+	/// a view from before the last change is laid out again, it should
+	/// have followed the change already (see View::shift)
+	pub fn swap_view(&mut self, view: &mut View) {
+		std::mem::swap(&mut self.cs, &mut view.cs);
+		std::mem::swap(&mut self.cached_cx, &mut view.cached_cx);
+		std::mem::swap(&mut self.visual, &mut view.visual);
+		std::mem::swap(&mut self.viewport, &mut view.viewport);
+		std::mem::swap(&mut self.selection, &mut view.selection);
+		let stale = view.laid_out != (self.version, self.relaid);
+		view.laid_out = (self.version, self.relaid);
+		if stale {
+			// the text changed through another window
+			let len = self.lines.len_chars();
+			self.cs = self.cs.min(len);
+			self.selection.anchor = self.selection.anchor.min(len);
+			self.selection.end = self.selection.end.min(len);
+			self.build_visual_line();
+			self.viewport.offset = self.viewport.offset.min(self.visual.len() - 1);
+			_ = self.viewport_fix_offset(Option::None);
+		}
+	}
+
     /// ensures buffer resizing is done correctly
     pub fn resize(&mut self, width : usize, height : usize) {
         self.viewport.width = width;
@@ -362,14 +935,96 @@ impl Buffer {
     fn viewport_fix_offset(&mut self, future_cy: Option<i32>) -> usize {
         // check if offset is correct 
         let cy = future_cy.unwrap_or(self.get_cursor_pos().1);
-        if cy < 0 {
-            self.viewport.offset -= (-cy) as usize;
-        } else if cy >= self.viewport.height as i32 {
-            self.viewport.offset += cy as usize - self.viewport.height + 1; 
+		// scrolloff rows stay between the cursor and the edges, unless the file ends
+		let height = self.viewport.height as i32;
+		let so = self.options.scrolloff.min(self.viewport.height.saturating_sub(1) / 2) as i32;
+        if cy < so {
+            self.viewport.offset = self.viewport.offset.saturating_sub((so - cy) as usize);
+        } else if cy >= height - so {
+			let row = self.viewport.offset as i32 + cy;
+			let last = (self.visual.len() as i32 - height).max(row - height + 1).max(self.viewport.offset as i32);
+            self.viewport.offset = (row + so - height + 1).min(last) as usize;
         }
+		// sideways, the cursor column has to be on screen
+		let cx = self.get_cursor_pos().0 as usize;
+		if self.options.wrap {
+			self.viewport.left = 0;
+		} else if cx < self.viewport.left {
+			self.viewport.left = cx;
+		} else if cx >= self.viewport.left + self.viewport.width {
+			self.viewport.left = cx + 1 - self.viewport.width;
+		}
 		self.viewport.offset
     }
 
+	/// scrolls the view so the cursor row is at the top, middle or bottom
+	pub fn viewport_place(&mut self, at: Anchor) {
+		let row = self.rope_to_visual(self.cs).1;
+		let height = self.viewport.height;
+		let so = self.options.scrolloff.min(height.saturating_sub(1) / 2);
+		self.viewport.offset = match at {
+			Anchor::Top => row.saturating_sub(so),
+			Anchor::Middle => row.saturating_sub(height / 2),
+			Anchor::Bottom => (row + so + 1).saturating_sub(height),
+		};
+	}
+
+	/// scrolls half a screen down (or up), the cursor keeps its row on screen
+	pub fn half_page(&mut self, down: bool) {
+		let amt = (self.viewport.height / 2).max(1);
+		let last = self.visual.len().saturating_sub(self.viewport.height).max(self.viewport.offset);
+		self.viewport.offset = match down {
+			true => (self.viewport.offset + amt).min(last),
+			false => self.viewport.offset.saturating_sub(amt),
+		};
+		// the cursor moves as far even if the view couldnt
+		let row = self.rope_to_visual(self.cs).1;
+		let to = match down {
+			true => (row + amt).min(self.visual.len() - 1),
+			false => row.saturating_sub(amt),
+		};
+		self.cursor_mv(Move::Exact(Direction::Vert, to as i32 - row as i32));
+	}
+
+	/// scrolls the view amt columns sideways, the cursor stays on screen.
+	/// returns false if the lines wrap and there's nothing to scroll
+	pub fn viewport_scroll(&mut self, amt: i32) -> bool {
+		if self.options.wrap { return false; }
+		self.viewport.left = self.viewport.left.saturating_add_signed(amt as isize);
+		let (cx, cy) = self.rope_to_visual(self.cs);
+		let last = self.viewport.left + self.viewport.width.max(1) - 1;
+		if cx < self.viewport.left || cx > last {
+			let col = cx.clamp(self.viewport.left, last);
+			self.cs = self.visual_to_rope(col, cy).min(self.end_of_row(cy));
+			self.cached_cx = self.get_cursor_pos().0 as usize;
+			// past the end of a short line, the view stops where the cursor does
+			self.viewport.left = self.viewport.left.min(self.cached_cx);
+		}
+		self.selection_check_update();
+		true
+	}
+
+	/// puts the cursor column at the left edge of the view, or the right one
+	pub fn viewport_align(&mut self, end: bool) -> bool {
+		if self.options.wrap { return false; }
+		let cx = self.get_cursor_pos().0 as usize;
+		self.viewport.left = match end {
+			true => (cx + 1).saturating_sub(self.viewport.width),
+			false => cx,
+		};
+		true
+	}
+
+	/// last place the cursor can go in visual row cy
+	fn end_of_row(&self, cy: usize) -> usize {
+		let vl = self.visual[cy];
+		let end = self.lines.line_to_char(vl.rope) + vl.offset + vl.len;
+		match cy + 1 == self.visual.len() {
+			true => end,
+			false => end.saturating_sub(1).max(self.lines.line_to_char(vl.rope) + vl.offset),
+		}
+	}
+
 	/// moves the viewport offset by amt 
 	pub fn viewport_mv(&mut self, amt: i32) {
 		let new_vp_off = self.viewport.offset as i32 + amt;
@@ -381,7 +1036,7 @@ impl Buffer {
 
 	/// returns true is the buffers state isnt saved
     pub fn is_modified(&self) -> bool {
-        self.history.is_dirty()
+        self.history.is_dirty() || self.options.format != self.saved_format
     }
 
 	/// starts a selection
@@ -394,6 +1049,7 @@ impl Buffer {
 	/// ends a selection
 	pub fn selection_end(&mut self) {
 		self.selection.active = false;
+		self.selection.expanded.clear();
 	}
 
 	/// updates the selection if needed
@@ -404,6 +1060,54 @@ impl Buffer {
 	}
 }
 
+/// chars that take a single column and cant join the grapheme before them,
+/// visual lines can be patched for those instead of rebuilt
+fn narrow(c: Option<char>) -> bool {
+	c.is_some_and(|c| c.is_ascii_graphic() || c == ' ')
+}
+
+/// nibble_to_char, but staying on the last nibble
+fn nibble_to_char_clamped(dump: &ropey::Rope, nib: usize) -> usize {
+	hex::nibble_to_char(dump, nib.min(hex::len(dump).saturating_sub(1)))
+}
+
+/// the text of a buffer while the history changes it, the syntax tree
+/// hears about every change so it can parse again only what changed,
+/// and the views of other windows get to follow it
+struct Tracked<'a> {
+	lines: &'a mut ropey::Rope,
+	syntax: Option<&'a mut Highlighter>,
+	shifts: &'a mut Vec<Shift>,
+}
+
+impl history::Text for Tracked<'_> {
+	fn replace(&mut self, at: usize, removed: &str, inserted: &str) {
+		let removed_chars = removed.chars().count();
+		if let Some(syntax) = &mut self.syntax {
+			syntax.edit(self.lines, at, removed_chars, inserted);
+		}
+		self.lines.replace(at, removed, inserted);
+		self.shifts.push(Shift { at, removed: removed_chars, inserted: inserted.chars().count() });
+	}
+}
+
+/// an edit as the cursors of other windows see it, in chars
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shift {
+	at: usize,
+	removed: usize,
+	inserted: usize,
+}
+
+impl Shift {
+	/// where cs ends up after the edit, inside removed text it goes to where the edit is
+	fn apply(&self, cs: usize) -> usize {
+		if cs < self.at { cs }
+		else if cs >= self.at + self.removed { cs - self.removed + self.inserted }
+		else { self.at }
+	}
+}
+
 #[derive(Default, Clone, Copy, Debug)]
 pub struct VisualLine {
 	pub offset   : usize,
@@ -412,20 +1116,51 @@ pub struct VisualLine {
     vis_width: usize,
 }
 
+/// a window into the buffer: where the cursor is and what is on screen.
+/// the buffer holds the one being used, see swap_view
+#[derive(Clone)]
+pub struct View {
+	cs: usize,
+	cached_cx: usize,
+	visual: Vec<VisualLine>,
+	viewport: ViewPort,
+	selection: Selection,
+	// version of the text the visual lines are for
+	laid_out: (usize, usize),
+}
+
+impl View {
+	/// moves the cursor and selection along with edits made through another window,
+	/// so they stay on the same text
+	pub fn shift(&mut self, shifts: &[Shift]) {
+		let sel = &mut self.selection;
+		for s in shifts {
+			self.cs = s.apply(self.cs);
+			sel.anchor = s.apply(sel.anchor);
+			sel.end = s.apply(sel.end);
+			for r in &mut sel.expanded {
+				*r = s.apply(r.start)..s.apply(r.end);
+			}
+		}
+	}
+}
+
 /// struct that dictates the way visual lines are printed to fit
 /// the screen vertically.
 /// 
-/// offset points to the first visual line that should be printed
+/// offset points to the first visual line that should be printed,
+/// left to the first column (lines only get wider than the screen with nowrap)
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct ViewPort {
 	pub offset : usize,
+	pub left   : usize,
     pub width  : usize,
     pub height : usize,
 }
 
 impl ViewPort {
     fn new(width: usize, height: usize) -> Self {
-        ViewPort { offset: 0, width, height}
+        ViewPort { offset: 0, left: 0, width, height}
     }
 }
 
@@ -439,9 +1174,26 @@ pub enum Move {
 	Word(i32)
 }
 
+/// where the cursor row goes on screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+	Top,
+	Middle,
+	Bottom,
+}
+
+/// every cursor move closes the current undo step
+/// moving around splits words, the other policies dont care
+impl EditAction for Move {
+	fn should_stash(&self, group: Group) -> bool {
+		group == Group::Word
+	}
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::scratch::Scratch;
 
     #[test]
     fn cursor_mv_exact_test() {
@@ -475,6 +1227,163 @@ mod tests {
 		assert_eq!(8, buf.visual_to_rope(9, 1))
 	}
 
+	#[test]
+	fn expandtab_test() {
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::from("ab"), 20, 5);
+		buf.options.tabstop = 8;
+		buf.refresh();
+		buf.insert_tab();
+		assert_eq!((8, 0), buf.rope_to_visual(buf.cs), "tabstop sets the width");
+		buf.backspace();
+		buf.options.expandtab = true;
+		buf.options.shiftwidth = 4;
+		buf.cursor_end();
+		buf.insert_tab();
+		assert_eq!("ab  ", buf.lines.to_string(), "up to the next stop");
+		buf.insert_tab();
+		assert_eq!("ab      ", buf.lines.to_string());
+		buf.backspace();
+		assert_eq!("ab  ", buf.lines.to_string(), "back one level");
+		buf.backspace();
+		assert_eq!("ab", buf.lines.to_string(), "stops at text");
+		buf.backspace();
+		assert_eq!("a", buf.lines.to_string());
+
+		buf.insert_tab();
+		buf.insert_tab();
+		buf.undo();
+		assert_eq!("a   ", buf.lines.to_string(), "a tab is one undo step");
+	}
+
+	#[test]
+	fn softtabstop_test() {
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::new(), 40, 5);
+		buf.options.tabstop = 8;
+		buf.options.softtabstop = 4;
+		buf.refresh();
+		buf.insert_tab();
+		assert_eq!("    ", buf.lines.to_string());
+		buf.insert_tab();
+		assert_eq!("\t", buf.lines.to_string(), "the spaces become a tab");
+		buf.insert_tab();
+		assert_eq!("\t    ", buf.lines.to_string());
+		buf.undo();
+		assert_eq!("\t", buf.lines.to_string(), "a tab is one undo step");
+
+		// backspace goes back the same way tab came
+		buf.backspace();
+		assert_eq!("    ", buf.lines.to_string(), "the tab is split");
+		buf.backspace();
+		assert_eq!("", buf.lines.to_string());
+
+		buf.insert('x');
+		buf.insert_tab();
+		buf.insert_tab();
+		assert_eq!("x\t", buf.lines.to_string(), "after text too");
+		buf.backspace();
+		assert_eq!("x   ", buf.lines.to_string());
+		buf.backspace();
+		assert_eq!("x", buf.lines.to_string(), "stops at text");
+	}
+
+	#[test]
+	fn retab_test() {
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::from("\tx\n  \ty\t\nz"), 20, 5);
+		buf.cursor_mv(Move::Exact(Direction::Vert, 1));
+		buf.cursor_end();
+		assert_eq!(8, buf.cs);
+		buf.options.expandtab = true;
+		buf.retab(Option::None);
+		assert_eq!("    x\n    y\t\nz", buf.lines.to_string(), "only the indentation");
+		assert_eq!(12, buf.cs, "still after the y");
+		buf.options.expandtab = false;
+		buf.retab(Some(2));
+		assert_eq!("\t\tx\n\t\ty\t\nz", buf.lines.to_string());
+		assert_eq!(2, buf.options.tabstop);
+		buf.undo();
+		assert_eq!("    x\n    y\t\nz", buf.lines.to_string(), "one undo step");
+	}
+
+	#[test]
+	fn nowrap_test() {
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::from("0123456789abc\nxy"), 5, 5);
+		assert_eq!(4, buf.visual.len());
+		assert!(!buf.viewport_scroll(1), "nothing to scroll with wrap");
+		buf.options.wrap = false;
+		buf.refresh();
+		assert_eq!(2, buf.visual.len());
+
+		buf.cursor_mv(Move::Exact(Direction::Horiz, 7));
+		assert_eq!(3, buf.viewport.left, "follows the cursor");
+		buf.cursor_home();
+		assert_eq!(0, buf.viewport.left);
+		assert!(buf.viewport_scroll(4));
+		assert_eq!((4, 4), (buf.viewport.left, buf.cs), "the cursor stays on screen");
+		buf.cursor_mv(Move::Exact(Direction::Horiz, 2));
+		assert!(buf.viewport_align(false));
+		assert_eq!(6, buf.viewport.left);
+		assert!(buf.viewport_align(true));
+		assert_eq!(2, buf.viewport.left);
+		buf.cursor_mv(Move::Exact(Direction::Vert, 1));
+		assert_eq!((2, 2), (buf.cs - 14, buf.viewport.left), "the end of a short line");
+		assert!(buf.viewport_scroll(1000));
+		assert_eq!((2, 2), (buf.cs - 14, buf.viewport.left), "no scrolling away from the cursor");
+		buf.cursor_mv(Move::Exact(Direction::Vert, -1));
+		assert!(buf.viewport_scroll(1000));
+		assert_eq!((13, 13), (buf.cs, buf.viewport.left));
+	}
+
+	#[test]
+	fn linebreak_test() {
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::from("ab cdefg"), 6, 5);
+		let lens = |buf: &Buffer| buf.visual.iter().map(|vl| vl.len).collect::<Vec<_>>();
+		assert_eq!(vec![6, 2], lens(&buf));
+		buf.options.linebreak = true;
+		buf.refresh();
+		assert_eq!(vec![3, 5], lens(&buf), "breaks after the blank");
+		buf.set_text(ropey::Rope::from("abc defghij"), 0);
+		assert_eq!(vec![4, 6, 1], lens(&buf));
+		buf.set_text(ropey::Rope::from("abcdefgh"), 0);
+		assert_eq!(vec![6, 2], lens(&buf), "no blank, breaks anywhere");
+	}
+
+	#[test]
+	fn scrolloff_test() {
+		let text: String = (0..20).map(|i| format!("{}\n", i)).collect();
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::from(text), 10, 10);
+		buf.options.scrolloff = 3;
+		buf.cursor_mv(Move::Exact(Direction::Vert, 7));
+		assert_eq!(1, buf.viewport.offset, "3 rows under the cursor");
+		buf.cursor_mv(Move::Exact(Direction::Vert, -4));
+		assert_eq!(0, buf.viewport.offset, "and above it");
+		buf.cursor_mv(Move::Exact(Direction::Vert, 16));
+		assert_eq!((11, 19), (buf.viewport.offset, buf.lines.char_to_line(buf.cs)), "but not past the end");
+
+		buf.viewport_place(Anchor::Top);
+		assert_eq!(16, buf.viewport.offset);
+		buf.viewport_place(Anchor::Middle);
+		assert_eq!(14, buf.viewport.offset);
+		buf.cursor_mv(Move::Exact(Direction::Vert, -10));
+		buf.viewport_place(Anchor::Bottom);
+		assert_eq!(3, buf.viewport.offset);
+	}
+
+	#[test]
+	fn half_page_test() {
+		let text: String = (0..20).map(|i| format!("{}\n", i)).collect();
+		let mut buf = Buffer::open("a".to_owned(), ropey::Rope::from(text), 10, 10);
+		buf.cursor_mv(Move::Exact(Direction::Vert, 2));
+		buf.half_page(true);
+		assert_eq!((5, 2), (buf.viewport.offset, buf.get_cursor_pos().1), "keeps its row");
+		buf.half_page(true);
+		buf.half_page(true);
+		assert_eq!((11, 17), (buf.viewport.offset, buf.rope_to_visual(buf.cs).1), "the cursor goes on");
+		buf.half_page(true);
+		assert_eq!(20, buf.rope_to_visual(buf.cs).1);
+		buf.half_page(false);
+		assert_eq!((6, 15), (buf.viewport.offset, buf.rope_to_visual(buf.cs).1));
+	}
+
     #[test]
     fn modified_indicator_test() {
         let mut buf = Buffer::new(20,20);
@@ -512,10 +1421,150 @@ mod tests {
         buf.redo();
     }
 	
+    #[test]
+    fn undo_restores_view_test() {
+        let mut buf = Buffer::new(20, 3);
+        for c in "1\n2\n3\n4\n5\n6".chars() { buf.insert(c); }
+        buf.cursor_mv(Move::Exact(Direction::Vert, -5));
+        buf.selection_begin();
+        buf.cursor_mv(Move::Exact(Direction::Horiz, 1));
+        buf.insert('x');
+        assert!(!buf.selection.active);
+        // scroll away, undo comes back with the selection
+        buf.cursor_end();
+        for _ in 0..5 { buf.cursor_mv(Move::Exact(Direction::Vert, 1)); }
+        assert_eq!(3, buf.viewport.offset);
+        buf.undo();
+        assert_eq!(0, buf.viewport.offset);
+        assert!(buf.selection.active);
+        assert_eq!(1..2, buf.selection.range_raw());
+        buf.redo();
+        assert_eq!(Some(2..3), buf.flash());
+    }
+
+    #[test]
+    fn undo_travel_test() {
+        let mut buf = Buffer::new(20, 20);
+        for c in "ab cd".chars() { buf.insert(c); }
+        // "cd" isnt stashed yet, it still counts as a step
+        assert!(buf.undo_travel(true, Span::Steps(1)));
+        assert_eq!("ab", buf.lines.to_string());
+        assert!(buf.undo_travel(false, Span::Writes(1)));
+        assert_eq!("ab cd", buf.lines.to_string());
+        assert_eq!(5, buf.cs);
+        assert!(!buf.undo_travel(false, Span::Steps(1)));
+    }
+
+    #[test]
+    fn write_to_test() {
+        let dir = Scratch::new("buffer");
+        let mut buf = Buffer::new(20, 20);
+        buf.insert('x');
+        // failed write: still modified, name unchanged
+        let res = buf.write_to(dir.join("missing").join("f.txt"), WriteOpts::default());
+        assert!(matches!(res, Err(WriteError::Io(_))));
+        assert!(buf.is_modified());
+        assert_eq!("new-file.md", buf.filename);
+        assert!(matches!(buf.write_to("", WriteOpts::default()), Err(WriteError::NoFilename)));
+        // good write
+        let path = dir.join("f.txt");
+        buf.write_to(&path, WriteOpts::default()).unwrap();
+        assert!(!buf.is_modified());
+        assert_eq!(path.to_string_lossy(), buf.filename);
+        assert_eq!("x", std::fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn disk_changed_test() {
+        let dir = Scratch::new("buffer-changed");
+        let path = dir.join("f.txt");
+        std::fs::write(&path, "mine").unwrap();
+
+        let mut buf = Buffer::from_file(
+            path.to_string_lossy().into_owned(), file::read(&path, encoding_rs::WINDOWS_1252).unwrap(), 20, 20
+        );
+        assert!(!buf.disk_changed());
+        // a formatter runs behind our back
+        std::fs::write(&path, "theirs!").unwrap();
+        assert!(buf.disk_changed());
+        buf.insert('x');
+        assert!(matches!(buf.write_to(&path, WriteOpts::default()), Err(WriteError::Changed)));
+        assert_eq!("theirs!", std::fs::read_to_string(&path).unwrap());
+        // reload picks up their version, and is undoable
+        buf.reload(encoding_rs::WINDOWS_1252).unwrap();
+        assert_eq!("theirs!", buf.lines.to_string());
+        assert!(!buf.is_modified());
+        assert!(!buf.disk_changed());
+        buf.undo();
+        assert_eq!("xmine", buf.lines.to_string());
+        // forcing clobbers
+        buf.write_to(&path, WriteOpts { force: true, ..Default::default() }).unwrap();
+        assert_eq!("xmine", std::fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn fileformat_test() {
+        let dir = Scratch::new("buffer-ff");
+        let path = dir.join("f.txt");
+        std::fs::write(&path, "a\r\nb\r\n").unwrap();
+
+        let mut buf = Buffer::from_file(
+            path.to_string_lossy().into_owned(), file::read(&path, encoding_rs::WINDOWS_1252).unwrap(), 20, 20
+        );
+        assert_eq!("a\nb\n", buf.lines.to_string());
+        assert_eq!(3, buf.visual.len());
+        buf.cursor_end();
+        assert_eq!(1, buf.cs, "\\r is not part of the line");
+        assert!(!buf.is_modified());
+        // changing the format is a change too
+        buf.options.format.eol = file::LineEnding::Unix;
+        assert!(buf.is_modified());
+        buf.write_to(&path, WriteOpts::default()).unwrap();
+        assert!(!buf.is_modified());
+        assert_eq!("a\nb\n", std::fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn hex_edit_test() {
+        let dir = Scratch::new("buffer-hex");
+        let path = dir.join("blob.bin");
+        let bytes = b"\x00\x01\r\n\xff".repeat(5);
+        std::fs::write(&path, &bytes).unwrap();
+
+        let stamp = Stamp::read(&path).unwrap();
+        let mut buf = Buffer::from_bytes(path.to_string_lossy().into_owned(), &bytes, stamp, 80, 20);
+        buf.insert('a');
+        buf.insert('Z');
+        buf.insert('b');
+        buf.delete(1, true);
+        buf.cursor_mv(Move::Exact(Direction::Horiz, 1));
+        buf.insert('f');
+        buf.sync_swap().unwrap();
+        assert!(!crate::swap::swap_path(&buf.filename).exists(), "hex views keep no swap");
+        assert!(buf.hex_find(&[0xff, 0x00]));
+        assert_eq!(4 * 2, hex::char_to_nibble(&buf.lines, buf.cs));
+        assert_eq!(vec![12..14, 15..17], buf.found(), "both bytes are highlighted");
+        assert!(!buf.hex_find(&[0x42]));
+        assert!(buf.found().is_empty());
+        buf.write_to(&path, WriteOpts::default()).unwrap();
+
+        let mut expect = bytes.clone();
+        expect[0] = 0xab;
+        expect[1] = 0x0f;
+        assert_eq!(expect, std::fs::read(&path).unwrap());
+        buf.undo();
+        assert_eq!(&[0xab, 0x01], &hex::parse(&buf.lines)[..2]);
+
+        assert!(buf.hex_find(&[0xff]));
+        buf.insert('0');
+        assert!(buf.found().is_empty(), "gone once the text changes");
+    }
+
 	#[test]
     fn home_end_test() {
         let mut buf = Buffer::new(5,5);
 		buf.lines = ropey::Rope::from("123456789");
+		buf.build_visual_line();
 		buf.cs = 8;
 		buf.cursor_home();
 		assert_eq!(buf.cs, 0);
@@ -524,6 +1573,7 @@ mod tests {
 		assert_eq!(buf.cs, 9);
 		//
 		buf.lines = ropey::Rope::from("123\n\n678");
+		buf.build_visual_line();
 		buf.cs = 7;
 		buf.cursor_home();
 		assert_eq!(buf.cs, 5);
@@ -531,4 +1581,91 @@ mod tests {
 		buf.cursor_end();
 		assert_eq!(buf.cs, 3);
     }
+
+	#[test]
+	fn syntax_selection_test() {
+		let text = ropey::Rope::from("fn a(x: u8) {}\nfn b() {}");
+		let mut buf = Buffer::open("a.rs".to_string(), text, 40, 10);
+		buf.cs = 5;
+		// typing keeps the tree in step
+		buf.insert('m');
+		buf.insert('u');
+		buf.insert('t');
+		buf.insert(' ');
+		assert!(buf.select_object(Object::Argument));
+		assert_eq!("mut x: u8", buf.selection.ctx(&buf.lines));
+
+		assert!(buf.expand_selection());
+		assert_eq!("(mut x: u8)", buf.selection.ctx(&buf.lines));
+		assert!(buf.shrink_selection());
+		assert_eq!("mut x: u8", buf.selection.ctx(&buf.lines));
+		buf.selection_end();
+		assert!(!buf.shrink_selection(), "a new selection forgets the old");
+
+		assert!(buf.function_jump(true));
+		assert_eq!(buf.lines.line_to_char(1), buf.cs);
+		assert!(!buf.function_jump(true));
+	}
+
+	#[test]
+	fn syntax_undo_test() {
+		let text = ropey::Rope::from("fn a() {}\nfn b() {}\n");
+		let mut buf = Buffer::open("a.rs".to_string(), text, 40, 10);
+		assert!(buf.function_jump(true));
+		buf.cs = 0;
+		buf.insert('/');
+		buf.insert('*');
+		assert!(!buf.function_jump(true), "all a comment");
+		// undo, redo and reloads tell the tree what they changed
+		buf.undo();
+		assert!(buf.function_jump(true));
+		buf.redo();
+		assert!(!buf.function_jump(true));
+		buf.set_text(ropey::Rope::from("fn a() {}\n\nfn c() {}\n"), 0);
+		assert!(buf.function_jump(true));
+		assert_eq!(buf.lines.line_to_char(2), buf.cs);
+
+		// no rows to show, nothing to parse up to
+		buf.resize(40, 0);
+		buf.insert('x');
+		buf.viewport.offset = 0;
+		buf.update_syntax();
+	}
+
+	#[test]
+	fn gutter_width_test() {
+		let mut buf = Buffer::new(20, 20);
+		assert_eq!(4, buf.gutter_width());
+		buf.lines = ropey::Rope::from("\n".repeat(123_456));
+		assert_eq!(7, buf.gutter_width(), "grows with the file");
+		buf.options.number = false;
+		buf.options.relativenumber = true;
+		assert_eq!(7, buf.gutter_width());
+		buf.options.relativenumber = false;
+		assert_eq!(0, buf.gutter_width());
+	}
+
+	#[test]
+	fn wide_chars_test() {
+		let mut buf = Buffer::open("a.txt".to_string(), ropey::Rope::from("日本語e\u{301}x\nabcdefgh"), 5, 5);
+		// 日本 fit on the first row, 語 goes to the next
+		assert_eq!((2, 4), (buf.visual[0].len, buf.visual[0].vis_width));
+		assert_eq!((5, 5), (buf.visual[1].len, buf.visual[1].vis_width));
+		buf.cursor_mv(Move::Exact(Direction::Horiz, 3));
+		assert_eq!((2, 1), buf.rope_to_visual(buf.cs));
+		buf.cursor_mv(Move::Exact(Direction::Horiz, 1));
+		assert_eq!(5, buf.cs, "e and its accent are one step");
+		assert_eq!((3, 1), buf.rope_to_visual(buf.cs));
+
+		// moving down lands on the column, not the char count
+		buf.cursor_mv(Move::Exact(Direction::Vert, 1));
+		assert_eq!((3, 2), buf.rope_to_visual(buf.cs));
+		buf.cursor_mv(Move::Exact(Direction::Vert, -2));
+		assert_eq!((2, 0), buf.rope_to_visual(buf.cs), "the middle of 本 goes to its start");
+
+		buf.cs = 5;
+		buf.delete(1, true);
+		assert_eq!("日本語x", buf.lines.line(0).to_string().trim_end());
+		assert_eq!(3, buf.cs);
+	}
 }
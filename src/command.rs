@@ -1,5 +1,16 @@
-use crate::{buffer::Buffer, Editor};
-use std::{collections::HashMap, collections::VecDeque, rc::Rc};
+use crate::{buffer::{Anchor, Buffer}, file::{self, WriteOpts}, history, options, swap, syntax, theme, tree::Object, undofile, window::Split, Editor};
+use std::{collections::HashMap, collections::VecDeque, path::Path, rc::Rc};
+
+/// what happens when the user picks an answer to a question
+pub type Answer = Rc<dyn Fn(&mut Editor) -> Result<(), String>>;
+
+/// a question shown in the prompt, answered with a single key
+struct Question {
+	msg: String,
+	answers: Vec<(char, Answer)>,
+	/// runs if the question is dropped with Esc
+	dismissed: Option<Answer>,
+}
 
 /*
 * prompt struct - stores info regarding the prompt prompt
@@ -10,21 +21,78 @@ pub struct Prompt {
 	history : VecDeque<String>,
 	curr	: isize,
 	next	: String,
+	// the last command as it was typed, spaces and all
+	line	: String,
+	question: Option<Question>,
 	comds  	: HashMap<&'static str, Rc<dyn Command>>
 }
 
 impl Prompt {
 	/// loads all known commands into the prompt
 	pub fn load_commands(&mut self) {
-		self.comds.insert(Write.name(), Rc::new(Write));
+		let (write, force_write) = (Write { force: false }, Write { force: true });
+		self.comds.insert(write.name(), Rc::new(write));
+		self.comds.insert(force_write.name(), Rc::new(force_write));
         self.comds.insert(Quit.name(), Rc::new(Quit));
         self.comds.insert(Edit.name(), Rc::new(Edit));
         self.comds.insert(Undo.name(), Rc::new(Undo));
         self.comds.insert(Redo.name(), Rc::new(Redo));
+		for g in [false, true] {
+			let (earlier, later) = (Earlier { g }, Later { g });
+			self.comds.insert(earlier.name(), Rc::new(earlier));
+			self.comds.insert(later.name(), Rc::new(later));
+		}
+        self.comds.insert(UndoList.name(), Rc::new(UndoList));
+        self.comds.insert(UndoTree.name(), Rc::new(UndoTree));
         self.comds.insert(Select.name(), Rc::new(Select));
         self.comds.insert(Copy.name(), Rc::new(Copy));
         self.comds.insert(Paste.name(), Rc::new(Paste));
         self.comds.insert(SwitchBuffer.name(), Rc::new(SwitchBuffer));
+        self.comds.insert(Set.name(), Rc::new(Set));
+        self.comds.insert(Syntax.name(), Rc::new(Syntax));
+        self.comds.insert(ColorScheme.name(), Rc::new(ColorScheme));
+		for forward in [false, true] {
+			let jump = FunctionJump { forward };
+			self.comds.insert(jump.name(), Rc::new(jump));
+		}
+		for obj in [Object::Function, Object::Class, Object::Argument] {
+			let select = SelectObject { obj };
+			self.comds.insert(select.name(), Rc::new(select));
+		}
+		for shrink in [false, true] {
+			let expand = Expand { shrink };
+			self.comds.insert(expand.name(), Rc::new(expand));
+		}
+		for at in [Anchor::Top, Anchor::Middle, Anchor::Bottom] {
+			let place = Place { at };
+			self.comds.insert(place.name(), Rc::new(place));
+		}
+		for right in [false, true] {
+			let scroll = SideScroll { right };
+			self.comds.insert(scroll.name(), Rc::new(scroll));
+		}
+		for end in [false, true] {
+			let align = SideAlign { end };
+			self.comds.insert(align.name(), Rc::new(align));
+		}
+		for vertical in [false, true] {
+			let split = SplitWindow { vertical };
+			self.comds.insert(split.name(), Rc::new(split));
+		}
+        self.comds.insert(TabNew.name(), Rc::new(TabNew));
+        self.comds.insert(TabClose.name(), Rc::new(TabClose));
+		for back in [false, true] {
+			let next = NextTab { back };
+			self.comds.insert(next.name(), Rc::new(next));
+		}
+        self.comds.insert(TabMove.name(), Rc::new(TabMove));
+        self.comds.insert(Close.name(), Rc::new(Close));
+        self.comds.insert(Only.name(), Rc::new(Only));
+        self.comds.insert(Retab.name(), Rc::new(Retab));
+        self.comds.insert(Recover.name(), Rc::new(Recover));
+        self.comds.insert(CheckTime.name(), Rc::new(CheckTime));
+        self.comds.insert(Hex.name(), Rc::new(Hex));
+        self.comds.insert(HexFind.name(), Rc::new(HexFind));
 	}
 
 	/// insert char in cmd.
@@ -93,10 +161,15 @@ impl Prompt {
     }
 
 	/// gets a command and updates history.
+	#[allow(clippy::ptr_arg)]
 	pub fn get_command(&mut self, args: &Vec<String>) -> Option<Rc<dyn Command>> {
 		if self.next.trim().is_empty() && self.curr == -1 {
 			return Option::None;
 		}
+		self.line = match self.curr {
+			-1 => self.next.clone(),
+			c => self.history[c as usize].clone(),
+		};
 		if self.curr == -1 {
 			self.history.push_front(self.next.trim().to_owned());
 		}
@@ -115,6 +188,48 @@ impl Prompt {
         self.cx = 0;
     }
 
+	/// asks the user something, the answer is picked with a single key.
+	/// answers get run by the editor like commands.
+	pub fn ask(&mut self, msg: String, answers: Vec<(char, Answer)>) {
+		self.question = Some(Question { msg, answers, dismissed: Option::None });
+		self.cx = 0;
+	}
+
+	/// what to do if the question being asked gets dropped
+	pub fn on_dismiss(&mut self, answer: Answer) {
+		if let Some(q) = &mut self.question {
+			q.dismissed = Some(answer);
+		}
+	}
+
+	pub fn has_msg(&self) -> bool {
+		self.msg.is_some()
+	}
+
+	pub fn is_asking(&self) -> bool {
+		self.question.is_some()
+	}
+
+	/// picks an answer, if c is one of the choices the question is over
+	pub fn answer(&mut self, c: char) -> Option<Answer> {
+		let answer = self.question.as_ref()?
+			.answers.iter()
+			.find(|(key, _)| *key == c)
+			.map(|(_, a)| a.clone())?;
+		self.question = Option::None;
+		Some(answer)
+	}
+
+	/// the line the last command came from, args split it on whitespace
+	pub fn line(&self) -> &str {
+		&self.line
+	}
+
+	/// drops the question without answering, gives back what to run for it
+	pub fn dismiss(&mut self) -> Option<Answer> {
+		self.question.take()?.dismissed
+	}
+
 	/// goes in the past.
 	pub fn history_back(&mut self) {
 		self.curr = (self.curr + 1).min(self.history.len() as isize - 1);
@@ -134,8 +249,20 @@ impl Prompt {
 		self.msg = Option::None;
 	}
 
+	/// the command typed so far, whatever message is on
+	pub fn typed(&self) -> &str {
+		match self.curr {
+			-1 => self.next.as_str(),
+			i => self.history[i as usize].as_str(),
+		}
+	}
+
 	/// returns the message that should currently be displayed on the prompt
+	#[allow(clippy::needless_lifetimes, clippy::needless_borrow)]
 	pub fn display<'a>(&'a self) -> (&'a str, usize) {
+		if let Some(q) = &self.question {
+			return (q.msg.as_str(), 0);
+		}
 		match &self.msg {
 			Option::None => if self.curr == -1 {
 					(&self.next.as_str(), self.cx)
@@ -155,6 +282,8 @@ impl Default for Prompt {
 			history : Default::default(),
 			curr	: -1,
 			next	: Default::default(),
+			line	: Default::default(),
+			question: Default::default(),
 			comds   : Default::default()
 		}
 	}
@@ -166,26 +295,27 @@ pub trait Command {
 }
 
 /// writes buffer to a file
-pub struct Write;
+/// `w!` writes even if the file was changed by someone else
+pub struct Write {
+	force: bool,
+}
 impl Command for Write {
-    fn name(&self) -> &'static str { "w" }
+    fn name(&self) -> &'static str { if self.force { "w!" } else { "w" } }
     fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
         if args.len() > 2 { return Err("too many args".to_owned()); }
 
+		let opts = WriteOpts { backup: ed.options.backup, force: self.force };
+		let undodir = ed.options.undodir.clone().filter(|_| ed.options.undofile);
         let buf = ed.active_buf_mut();
 		let filename = args.get(1).unwrap_or(&buf.filename).clone();
-		if buf.filename != filename {
-			buf.filename = filename.to_string();
-		}
-		buf.save();
-        let mut wr = std::io::BufWriter::new(
-            convert_res(std::fs::File::create(&filename))
-        ?);
-
-        convert_res(buf.lines.write_to(&mut wr))?;
-        convert_res(std::io::Write::flush(&mut wr))?;
+        buf.write_to(filename, opts).map_err(|e| e.to_string())?;
 
-        Ok(())
+		// the undo history of a hex dump is no use for the text
+		if let (Some(dir), Some(stamp), false) = (undodir, buf.stamp, buf.hex) {
+			undofile::write(&dir, &buf.filename, stamp.hash(), buf.history())
+				.map_err(|e| format!("written, but the undo file wasnt: {}", e))?;
+		}
+		Ok(())
     }
 }
 
@@ -196,6 +326,10 @@ impl Command for Quit {
     fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
         if args.len() > 1 { return Err("too many args".to_owned()); }
 
+		// quitting throws the changes away, so do their swaps
+		for buf in &mut ed.bufs {
+			buf.remove_swap();
+		}
         ratatui::restore();
         ed.alive = false;
         Ok(())
@@ -211,22 +345,133 @@ impl Command for Edit {
         if args.len() > 2 { return Err("too many args".to_owned()); }
         if args.len() < 2 { return Err("no file was specified".to_owned()); }
         
-        let reader = std::io::BufReader::new(
-            convert_res(std::fs::File::open(args[1].to_owned()))?
-        );
+        let file = convert_res(file::read(Path::new(&args[1]), ed.options.fallbackencoding))?;
+		let binary = file.binary;
         let (w, h) = ed.get_size();
-        ed.bufs.push(Buffer::open(args[1].clone(),
-            convert_res(ropey::Rope::from_reader(reader))?,
-            w, h
-        ));
-        ed.active_buf = ed.bufs.len() -1; 
+		// the file is open already, the swap belongs to that buffer
+		let open = ed.bufs.iter().any(|b| b.options.swapfile && file::same_file(&b.filename, &args[1]));
+		let mut buf = Buffer::from_file(args[1].clone(), file, w, h);
+		buf.options.swapfile = !open;
+		// a missing or outdated undo file just means starting fresh
+		if let (Some(dir), Some(stamp), true) = (&ed.options.undodir, buf.stamp, ed.options.undofile) {
+			if let Ok(history) = undofile::read(dir, &args[1], stamp.hash()) {
+				buf.restore_history(history);
+			}
+		}
+        ed.bufs.push(buf);
+        ed.show_buf(ed.bufs.len() - 1);
+
+		if let (false, Ok(swap)) = (open, swap::read(&args[1])) {
+			ask_swap(ed, swap);
+		} else if binary {
+			return Err("binary file, opened readonly (:hex to edit it)".to_owned());
+		}
+        Ok(())
+    }
+}
+
+/// found a swap while opening a file, ask what to do with it.
+/// until answered the buffer leaves the swap alone, and so it stays
+/// if the question is ignored (Esc) or the swap's editor is still running.
+fn ask_swap(ed: &mut Editor, swap: swap::Swap) {
+	ed.active_buf_mut().options.swapfile = false;
+
+	let (stale, pid) = (swap.is_stale(), swap.pid);
+	let owner = if stale {
+		String::new()
+	} else {
+		format!(" (in use by pid {})", pid)
+	};
+	let recover: Answer = Rc::new(|ed| Recover.run(vec!["recover".to_owned()], ed));
+	let delete: Answer = Rc::new(move |ed| {
+		if !stale { return Err(format!("swap file is in use by pid {}, left it alone", pid)); }
+		let buf = ed.active_buf_mut();
+		buf.options.swapfile = true;
+		buf.remove_swap();
+		Ok(())
+	});
+	let readonly: Answer = Rc::new(|ed| {
+		let buf = ed.active_buf_mut();
+		buf.readonly_for_swap = !buf.options.readonly;
+		buf.options.readonly = true;
+		Ok(())
+	});
+	ed.prompt.ask(
+		format!("found swap file{}: [r]ecover, [d]elete it, [o]pen readonly", owner),
+		vec![('r', recover), ('d', delete), ('o', readonly)]
+	);
+	ed.prompt.on_dismiss(Rc::new(|_| Err("swap file left alone, this buffer keeps none".to_owned())));
+}
+
+/// restores the active buffer from its swap file
+pub struct Recover;
+impl Command for Recover {
+    fn name(&self) -> &'static str { "recover" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
 
+		let buf = ed.active_buf_mut();
+		let swap = swap::read(&buf.filename)
+			.map_err(|e| format!("cant read swap file: {}", e))?;
+		// the swap is ours from now on, unless its editor is still running
+		buf.options.swapfile = swap.is_stale();
+		buf.set_text(swap.text, swap.cs);
+		// readonly only because of the swap, not for its own reasons
+		if std::mem::take(&mut buf.readonly_for_swap) { buf.options.readonly = false; }
         Ok(())
     }
 }
 
+/// looks for open files that were changed on disk by someone else.
+/// unmodified buffers get reloaded (with autoread), otherwise it asks.
+pub struct CheckTime;
+impl Command for CheckTime {
+    fn name(&self) -> &'static str { "checktime" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		ed.check_time();
+        Ok(())
+    }
+}
+
+/// opens a file in a hex view, byte exact.
+/// without arguments it opens the file of the active buffer.
+pub struct Hex;
+impl Command for Hex {
+    fn name(&self) -> &'static str { "hex" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+
+		let filename = args.get(1).unwrap_or(&ed.active_buf().filename).clone();
+		let bytes = convert_res(std::fs::read(&filename))?;
+		let stamp = convert_res(file::Stamp::read(Path::new(&filename)))?;
+        let (w, h) = ed.get_size();
+        ed.bufs.push(Buffer::from_bytes(filename, &bytes, stamp, w, h));
+        ed.show_buf(ed.bufs.len() - 1);
+        Ok(())
+    }
+}
+
+/// jumps to the next occurrence of some bytes in a hex view, eg `hf 7f 45 4c 46`
+pub struct HexFind;
+impl Command for HexFind {
+    fn name(&self) -> &'static str { "hf" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() < 2 { return Err("no bytes to look for".to_owned()); }
+		if !ed.active_buf().hex { return Err("not a hex view".to_owned()); }
+
+		let needle = crate::hex::parse_pattern(&args[1..].join(""))
+			.ok_or("invalid bytes, use hex digits like 7f45".to_owned())?;
+		match ed.active_buf_mut().hex_find(&needle) {
+			true => Ok(()),
+			false => Err("pattern not found".to_owned()),
+		}
+    }
+}
+
 /// opens an existing buffer and sets it as the active one.
 pub struct SwitchBuffer;
+#[allow(clippy::needless_return)]
 impl Command for SwitchBuffer {
     fn name(&self) -> &'static str { "b" }
     fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
@@ -235,7 +480,7 @@ impl Command for SwitchBuffer {
         if args.len() == 1 {
             let (w, h) = ed.get_size();
             ed.bufs.push(Buffer::new(w, h));
-            ed.active_buf = ed.bufs.len() -1; 
+            ed.show_buf(ed.bufs.len() - 1);
             return Ok(());
         }
 
@@ -245,7 +490,7 @@ impl Command for SwitchBuffer {
                 if v >= ed.bufs.len() {
                     return Err("that buffer isnt open".to_owned());
                 } else {
-                    ed.active_buf = v;
+                    ed.show_buf(v);
                     Ok(())
                 }
             },
@@ -253,13 +498,22 @@ impl Command for SwitchBuffer {
     }
 }
 
-/// same as hitting the undo button, maybe useful someday
+/// same as hitting the undo button, maybe useful someday.
+/// `undo <n>` jumps to state n of the undo tree.
 pub struct Undo;
 impl Command for Undo {
     fn name(&self) -> &'static str { "undo" }
     fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
-        if args.len() > 1 { return Err("too many args".to_owned()); }
-        ed.active_buf_mut().undo();
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		let buf = ed.active_buf_mut();
+		match args.get(1) {
+			Option::None => buf.undo(),
+			Some(n) => {
+				let seq = n.parse::<usize>().map_err(|_| "invalid argument".to_owned())?;
+				if seq >= buf.history().len() { return Err("no such state".to_owned()); }
+				buf.undo_goto(seq);
+			}
+		}
         Ok(())
     }
 }
@@ -275,6 +529,79 @@ impl Command for Redo {
     }
 }
 
+/// goes back in the undo history, in the order changes were made
+/// (so across branches): `earlier 3` steps, `earlier 10m` in time,
+/// `earlier 2f` file writes. `g-` is `earlier 1`.
+pub struct Earlier {
+	g: bool,
+}
+impl Command for Earlier {
+    fn name(&self) -> &'static str { if self.g { "g-" } else { "earlier" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+		let span = parse_span(&args, self.g)?;
+		match ed.active_buf_mut().undo_travel(true, span) {
+			true => Ok(()),
+			false => Err("already at oldest change".to_owned()),
+		}
+    }
+}
+
+/// the opposite of earlier, `g+` is `later 1`
+pub struct Later {
+	g: bool,
+}
+impl Command for Later {
+    fn name(&self) -> &'static str { if self.g { "g+" } else { "later" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+		let span = parse_span(&args, self.g)?;
+		match ed.active_buf_mut().undo_travel(false, span) {
+			true => Ok(()),
+			false => Err("already at newest change".to_owned()),
+		}
+    }
+}
+
+/// the count of earlier/later, one step if missing
+fn parse_span(args: &[String], g: bool) -> Result<history::Span, String> {
+	let max = if g { 1 } else { 2 };
+	if args.len() > max { return Err("too many args".to_owned()); }
+	match args.get(1) {
+		Option::None => Ok(history::Span::Steps(1)),
+		Some(arg) => history::Span::parse(arg)
+			.ok_or(format!("invalid count: {}, use eg 3, 10s, 5m, 2h, 1d or 2f", arg)),
+	}
+}
+
+/// lists the end of every branch of the undo tree:
+/// state number, changes from the opened file and age
+pub struct UndoList;
+impl Command for UndoList {
+    fn name(&self) -> &'static str { "undolist" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		let leaves = ed.active_buf().history().leaves();
+		if leaves.is_empty() { return Err("nothing to undo".to_owned()); }
+
+		let list = leaves.iter()
+			.map(|s| format!("{}: {} changes, {} ago", s.seq, s.changes, history::ago(s.time)))
+			.collect::<Vec<_>>()
+			.join(" | ");
+		ed.prompt.msg(list);
+		Ok(())
+    }
+}
+
+/// shows the undo tree in a popup, moving around in it previews the states
+pub struct UndoTree;
+impl Command for UndoTree {
+    fn name(&self) -> &'static str { "undotree" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		ed.open_undo_tree();
+        Ok(())
+    }
+}
+
 /// starts a selection, might remove
 pub struct Select;
 impl Command for Select {
@@ -297,9 +624,12 @@ impl Command for Paste {
         if args.len() > 1 { return Err("too many args".to_owned()); }
 		let text = ed.reg.clone();
 		let buf = ed.active_buf_mut();
+		// a paste is undone in one go, spaces or not
+		buf.begin_group();
 		for c in text.chars() {
 			buf.insert(c);
 		}
+		buf.end_group();
 		buf.selection_end();
         Ok(())
     }
@@ -320,6 +650,333 @@ impl Command for Copy {
     }
 }
 
+/// changes editor or buffer options, eg `set backup` or `set updatetime=1000`
+pub struct Set;
+impl Command for Set {
+    fn name(&self) -> &'static str { "set" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() < 2 { return Err("no option specified".to_owned()); }
+		for (i, arg) in args.iter().enumerate().skip(1) {
+			let (name, value) = options::split(arg);
+			// the status line format can have spaces, it takes the rest of the line
+			if matches!(name, "statusline" | "stl") && value.is_some() {
+				let line = ed.prompt.line().to_owned();
+				let arg = skip_args(&line, i);
+				let (name, value) = options::split(arg);
+				return ed.options.set(name, value).unwrap_or(Err(format!("unknown option: {}", arg)));
+			}
+			// buffer options shadow editor ones
+			let res = match ed.active_buf_mut().options.set(name, value) {
+				Some(res) => res,
+				Option::None => ed.options.set(name, value)
+					.unwrap_or(Err(format!("unknown option: {}", arg))),
+			};
+			res?;
+		}
+		// tabstop and friends change how the text is laid out
+		ed.active_buf_mut().refresh();
+        Ok(())
+    }
+}
+
+/// sets the highlighting of the active buffer, `syntax off` turns it off.
+/// without arguments it tells which one is on.
+pub struct Syntax;
+impl Command for Syntax {
+    fn name(&self) -> &'static str { "syntax" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		let buf = ed.active_buf_mut();
+		match args.get(1).map(|s| s.as_str()) {
+			Option::None => {
+				let name = buf.syntax.as_ref().map_or("off", |s| s.lang().name());
+				ed.prompt.msg(format!("syntax: {}", name));
+			},
+			Some("off") => buf.syntax = Option::None,
+			Some("on") => buf.detect_syntax(),
+			Some(name) => {
+				let lang = syntax::by_name(name).ok_or(format!("unknown syntax: {}", name))?;
+				buf.syntax = Some(syntax::Highlighter::new(lang));
+			},
+		}
+        Ok(())
+    }
+}
+
+/// switches the theme, `colorscheme` alone tells which one is on.
+/// takes a built in theme, the name of a file in the themes dir or a path.
+pub struct ColorScheme;
+impl Command for ColorScheme {
+    fn name(&self) -> &'static str { "colorscheme" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		match args.get(1) {
+			Option::None => {
+				let builtin = theme::builtin_names().collect::<Vec<_>>().join(", ");
+				ed.prompt.msg(format!("colorscheme: {} (built in: {})", ed.theme.name, builtin));
+			},
+			Some(name) => ed.theme = theme::Theme::load(name)?,
+		}
+        Ok(())
+    }
+}
+
+/// `]f` jumps to the next function, `[f` to the previous one
+pub struct FunctionJump {
+	forward: bool,
+}
+impl Command for FunctionJump {
+    fn name(&self) -> &'static str { if self.forward { "]f" } else { "[f" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		no_tree(ed)?;
+		match ed.active_buf_mut().function_jump(self.forward) {
+			true => Ok(()),
+			false => Err("no more functions".to_owned()),
+		}
+    }
+}
+
+/// selects the function (`af`), class (`ac`) or argument (`aa`) around the cursor.
+/// again on a selection it picks the one around that.
+pub struct SelectObject {
+	obj: Object,
+}
+impl Command for SelectObject {
+    fn name(&self) -> &'static str {
+		match self.obj {
+			Object::Function => "af",
+			Object::Class => "ac",
+			Object::Argument => "aa",
+		}
+	}
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		no_tree(ed)?;
+		match ed.active_buf_mut().select_object(self.obj) {
+			true => Ok(()),
+			false => Err(format!("not inside a {:?}", self.obj).to_lowercase()),
+		}
+    }
+}
+
+/// grows the selection to the enclosing syntax node, `shrink` goes back.
+/// also alt+up and alt+down.
+pub struct Expand {
+	shrink: bool,
+}
+impl Command for Expand {
+    fn name(&self) -> &'static str { if self.shrink { "shrink" } else { "expand" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		no_tree(ed)?;
+		let buf = ed.active_buf_mut();
+		match self.shrink {
+			true if !buf.shrink_selection() => Err("nothing to shrink".to_owned()),
+			false if !buf.expand_selection() => Err("already the whole file".to_owned()),
+			_ => Ok(()),
+		}
+    }
+}
+
+/// scrolls the cursor line to the top (`zt`), middle (`zz`) or bottom (`zb`) of the view
+pub struct Place {
+	at: Anchor,
+}
+impl Command for Place {
+    fn name(&self) -> &'static str {
+		match self.at {
+			Anchor::Top => "zt",
+			Anchor::Middle => "zz",
+			Anchor::Bottom => "zb",
+		}
+	}
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		ed.active_buf_mut().viewport_place(self.at);
+        Ok(())
+    }
+}
+
+/// scrolls the view sideways with nowrap, `zh`/`zl` by a column or `zl 10` by ten
+pub struct SideScroll {
+	right: bool,
+}
+impl Command for SideScroll {
+    fn name(&self) -> &'static str { if self.right { "zl" } else { "zh" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		let count = match args.get(1).map(|v| v.parse::<i32>()) {
+			Option::None => 1,
+			Some(Ok(n)) => n,
+			Some(Err(_)) => return Err(format!("invalid count: {}", args[1])),
+		};
+		let amt = if self.right { count } else { -count };
+		match ed.active_buf_mut().viewport_scroll(amt) {
+			true => Ok(()),
+			false => Err("lines wrap, set nowrap to scroll sideways".to_owned()),
+		}
+    }
+}
+
+/// `zs` scrolls the cursor to the left edge of the view, `ze` to the right one
+pub struct SideAlign {
+	end: bool,
+}
+impl Command for SideAlign {
+    fn name(&self) -> &'static str { if self.end { "ze" } else { "zs" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		match ed.active_buf_mut().viewport_align(self.end) {
+			true => Ok(()),
+			false => Err("lines wrap, set nowrap to scroll sideways".to_owned()),
+		}
+    }
+}
+
+/// splits the window in two, one above the other or side by side with `vsplit`.
+/// with a file name the new window opens it
+pub struct SplitWindow {
+	vertical: bool,
+}
+impl Command for SplitWindow {
+    fn name(&self) -> &'static str { if self.vertical { "vsplit" } else { "split" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		ed.split(if self.vertical { Split::Vertical } else { Split::Horizontal })?;
+		match args.get(1) {
+			Some(file) => Edit.run(vec!["e".to_owned(), file.clone()], ed),
+			Option::None => Ok(()),
+		}
+    }
+}
+
+/// closes the active window, the buffer stays open
+pub struct Close;
+impl Command for Close {
+    fn name(&self) -> &'static str { "close" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		ed.close(ed.active_win)
+    }
+}
+
+/// closes every window but the active one
+pub struct Only;
+impl Command for Only {
+    fn name(&self) -> &'static str { "only" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		ed.only();
+        Ok(())
+    }
+}
+
+/// opens a tab page after this one, on a new buffer or the file given
+pub struct TabNew;
+impl Command for TabNew {
+    fn name(&self) -> &'static str { "tabnew" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		ed.tab_new();
+		match args.get(1) {
+			Some(file) => Edit.run(vec!["e".to_owned(), file.clone()], ed),
+			Option::None => {
+				ed.new_buf();
+				Ok(())
+			},
+		}
+    }
+}
+
+/// closes the tab page and its windows, the buffers stay open
+pub struct TabClose;
+impl Command for TabClose {
+    fn name(&self) -> &'static str { "tabclose" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 1 { return Err("too many args".to_owned()); }
+		ed.tab_close()
+    }
+}
+
+/// `gt` goes to the next tab page, `gT` to the previous one, both wrap around.
+/// `gt 3` goes to the third
+pub struct NextTab {
+	back: bool,
+}
+impl Command for NextTab {
+    fn name(&self) -> &'static str { if self.back { "gT" } else { "gt" } }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		let len = ed.tabs.len();
+		let t = match args.get(1).map(|v| v.parse::<usize>()) {
+			Option::None if self.back => (ed.active_tab + len - 1) % len,
+			Option::None => (ed.active_tab + 1) % len,
+			Some(Ok(n)) if (1..=len).contains(&n) => n - 1,
+			Some(_) => return Err(format!("no tab {}", args[1])),
+		};
+		ed.goto_tab(t);
+        Ok(())
+    }
+}
+
+/// moves the tab page: `tabmove 0` to the front, `tabmove` to the end,
+/// `tabmove +1`/`tabmove -1` one place over
+pub struct TabMove;
+impl Command for TabMove {
+    fn name(&self) -> &'static str { "tabmove" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		let invalid = || format!("invalid position: {}", args[1]);
+		let to = match args.get(1).map(String::as_str) {
+			Option::None => ed.tabs.len() - 1,
+			Some(v) if v.starts_with(['+', '-']) => {
+				let amt = v.parse::<isize>().map_err(|_| invalid())?;
+				ed.active_tab.saturating_add_signed(amt)
+			},
+			Some(v) => v.parse().map_err(|_| invalid())?,
+		};
+		ed.tab_move(to);
+        Ok(())
+    }
+}
+
+/// converts the indentation to tabs or spaces, as expandtab says.
+/// `retab 8` reads the tabs as before and sets tabstop to 8 after.
+pub struct Retab;
+impl Command for Retab {
+    fn name(&self) -> &'static str { "retab" }
+    fn run(&self, args: Vec<String>, ed : &mut Editor) -> Result<(), String> {
+        if args.len() > 2 { return Err("too many args".to_owned()); }
+		if ed.active_buf().hex { return Err("cant retab a hex view".to_owned()); }
+		let tabstop = match args.get(1).map(|v| v.parse::<usize>()) {
+			Option::None => Option::None,
+			Some(Ok(ts)) if ts > 0 => Some(ts),
+			Some(_) => return Err(format!("invalid tabstop: {}", args[1])),
+		};
+		ed.active_buf_mut().retab(tabstop);
+        Ok(())
+    }
+}
+
+/// the syntax commands need a language with a grammar
+fn no_tree(ed: &Editor) -> Result<(), String> {
+	match ed.active_buf().syntax.as_ref().is_some_and(|s| s.has_tree()) {
+		true => Ok(()),
+		false => Err("no syntax tree for this buffer".to_owned()),
+	}
+}
+
+/// what is left of line after the first n args, as it was typed
+fn skip_args(line: &str, n: usize) -> &str {
+	let space = |c: char| c.is_ascii_whitespace();
+	let mut rest = line.trim_start_matches(space);
+	for _ in 0..n {
+		rest = rest.trim_start_matches(|c| !space(c)).trim_start_matches(space);
+	}
+	rest
+}
+
 /// helper fn to convert errors nicely and reduce code verbosity
 fn convert_res<T>(res : std::io::Result<T>) -> Result<T, String> {
     match res {
@@ -333,6 +990,7 @@ mod tests {
 	use super::*;
 
 	#[test]
+	#[allow(clippy::field_reassign_with_default)]
 	fn no_history_test() {
 		let mut p = Prompt::default();
 		p.next = "bang".to_owned();
@@ -346,6 +1004,7 @@ mod tests {
 	}
 
 	#[test]
+	#[allow(clippy::field_reassign_with_default)]
 	fn back_after_typing_test() {
 		let mut p = Prompt::default();
 		p.next = "bang".to_owned();
@@ -371,6 +1030,7 @@ mod tests {
 	}
 
 	#[test]
+	#[allow(clippy::field_reassign_with_default)]
 	fn edit_history_test() {
 		let mut p = Prompt::default();
 		p.next = "comando 1".to_string();
@@ -387,6 +1047,71 @@ mod tests {
 		assert_eq!("comando 21", p.display().0);
 	}
 
+	#[test]
+	fn set_statusline_test() {
+		let mut ed = crate::Editor::default();
+		ed.bufs.push(Buffer::new(20, 5));
+		ed.prompt.next = " set nu  stl=%m  %n ".to_owned();
+		let args = ed.prompt.parse();
+		let cmd = ed.prompt.get_command(&args).unwrap();
+		cmd.run(args, &mut ed).unwrap();
+		assert_eq!("%m  %n ", ed.options.statusline);
+	}
+
+	#[test]
+	fn question_test() {
+		let mut p = Prompt::default();
+		let answer: Answer = Rc::new(|_| Ok(()));
+		p.ask("sure?".to_owned(), vec![('y', answer)]);
+		assert!(p.is_asking());
+		assert_eq!("sure?", p.display().0);
+		assert!(p.answer('n').is_none());
+		assert!(p.is_asking());
+		assert!(p.answer('y').is_some());
+		assert!(!p.is_asking());
+
+		p.ask("sure?".to_owned(), vec![]);
+		assert!(p.dismiss().is_none());
+		p.ask("sure?".to_owned(), vec![]);
+		p.on_dismiss(Rc::new(|_| Ok(())));
+		assert!(p.dismiss().is_some());
+		assert!(!p.is_asking());
+	}
+
+	#[test]
+	fn swap_question_test() {
+		let dir = crate::scratch::Scratch::new("command-swap");
+		let path = dir.join("a.txt").to_string_lossy().into_owned();
+		std::fs::write(&path, "old").unwrap();
+		// written by this process, so its editor is still running
+		swap::write(&path, 0, &ropey::Rope::from("new")).unwrap();
+		let edit = |ed: &mut Editor| Edit.run(vec!["e".to_owned(), path.clone()], ed).unwrap();
+
+		let mut ed = Editor::default();
+		edit(&mut ed);
+		assert!(ed.prompt.is_asking());
+		assert!(ed.prompt.dismiss().unwrap()(&mut ed).is_err());
+		assert!(!ed.active_buf().options.swapfile, "ignoring the question leaves the swap alone");
+		assert!(ed.prompt.answer('d').is_none());
+
+		edit(&mut ed);
+		assert!(ed.prompt.answer('d').unwrap()(&mut ed).is_err(), "its editor is running");
+		assert!(swap::read(&path).is_ok());
+
+		edit(&mut ed);
+		ed.prompt.answer('o').unwrap()(&mut ed).unwrap();
+		assert!(ed.active_buf().options.readonly);
+		Recover.run(vec!["recover".to_owned()], &mut ed).unwrap();
+		assert_eq!("new", ed.active_buf().lines.to_string());
+		assert!(!ed.active_buf().options.readonly);
+		assert!(!ed.active_buf().options.swapfile, "not adopted while in use");
+
+		// readonly for another reason stays that way
+		ed.active_buf_mut().options.readonly = true;
+		Recover.run(vec!["recover".to_owned()], &mut ed).unwrap();
+		assert!(ed.active_buf().options.readonly);
+	}
+
 	#[test]
 	fn run_from_history_test() {
 		let mut p = Prompt::default();
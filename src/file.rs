@@ -0,0 +1,614 @@
+/*
+* file stuff - everything that touches the disk goes through here
+*/
+use encoding_rs::Encoding;
+use std::{fmt, fs, io, path::{Path, PathBuf}, time::SystemTime};
+
+/// what can go wrong when writing a buffer out
+#[derive(Debug)]
+pub enum WriteError {
+	/// there is no name to write the buffer to
+	NoFilename,
+	/// the buffer is marked readonly
+	ReadOnly,
+	/// someone else changed the file since we read it
+	Changed,
+	Io(io::Error),
+}
+
+impl fmt::Display for WriteError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			WriteError::NoFilename => write!(f, "no file name"),
+			WriteError::ReadOnly => write!(f, "buffer is readonly (see :set noreadonly)"),
+			WriteError::Changed => write!(f, "file changed on disk since reading it (:w! to overwrite)"),
+			WriteError::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+impl std::error::Error for WriteError {}
+
+impl From<io::Error> for WriteError {
+	fn from(e: io::Error) -> Self {
+		WriteError::Io(e)
+	}
+}
+
+/// how a buffer gets written
+#[derive(Default, Clone, Copy)]
+pub struct WriteOpts {
+	/// keep the old file as `file~`
+	pub backup: bool,
+	/// write even if the file changed on disk
+	pub force: bool,
+}
+
+/// what a file looked like the last time we read or wrote it.
+/// mtime and size are the quick check, the hash tells if the content really changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stamp {
+	mtime: Option<SystemTime>,
+	size: u64,
+	hash: u64,
+}
+
+impl Stamp {
+	/// stamps the file currently at path
+	pub fn read(path: &Path) -> io::Result<Stamp> {
+		let bytes = fs::read(path)?;
+		Ok(Stamp::new(path, &bytes))
+	}
+
+	fn new(path: &Path, bytes: &[u8]) -> Stamp {
+		Stamp {
+			mtime: fs::metadata(path).and_then(|m| m.modified()).ok(),
+			size: bytes.len() as u64,
+			hash: hash(bytes),
+		}
+	}
+
+	/// hash of the content
+	pub fn hash(&self) -> u64 {
+		self.hash
+	}
+
+	/// true if the file at path doesnt have the content we know about.
+	/// a file that cant be read (eg deleted) doesnt count as changed.
+	pub fn changed(&self, path: &Path) -> bool {
+		let Ok(meta) = fs::metadata(path) else { return false; };
+		if meta.len() == self.size && meta.modified().ok() == self.mtime {
+			return false;
+		}
+		// touched, but maybe not changed
+		Stamp::read(path).is_ok_and(|s| s.hash != self.hash)
+	}
+}
+
+/// how lines end in a file. inside the editor they always end with `\n`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+	#[default]
+	Unix,
+	Dos,
+	Mac,
+}
+
+impl LineEnding {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			LineEnding::Unix => "\n",
+			LineEnding::Dos => "\r\n",
+			LineEnding::Mac => "\r",
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			LineEnding::Unix => "unix",
+			LineEnding::Dos => "dos",
+			LineEnding::Mac => "mac",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<LineEnding> {
+		match name {
+			"unix" => Some(LineEnding::Unix),
+			"dos" => Some(LineEnding::Dos),
+			"mac" => Some(LineEnding::Mac),
+			_ => Option::None,
+		}
+	}
+
+	/// the ending most lines use, mixed files get written back with it
+	fn detect(text: &str) -> LineEnding {
+		let crlf = text.matches("\r\n").count();
+		let lf = text.matches('\n').count() - crlf;
+		let cr = text.matches('\r').count() - crlf;
+		if crlf > lf && crlf >= cr {
+			LineEnding::Dos
+		} else if cr > lf && cr > crlf {
+			LineEnding::Mac
+		} else {
+			LineEnding::Unix
+		}
+	}
+}
+
+/// the shape of a file on disk, which is restored when writing it back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+	pub eol: LineEnding,
+	/// file starts with a byte order mark
+	pub bom: bool,
+	pub encoding: &'static Encoding,
+}
+
+impl Default for Format {
+	fn default() -> Self {
+		Format { eol: LineEnding::default(), bom: false, encoding: encoding_rs::UTF_8 }
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.eol.name())?;
+		if self.encoding != encoding_rs::UTF_8 {
+			write!(f, ",{}", self.encoding.name().to_lowercase())?;
+		}
+		if self.bom { write!(f, ",bom")?; }
+		Ok(())
+	}
+}
+
+/// looks up an encoding we can both read and write, eg `latin1` or `utf-16le`
+pub fn encoding_for(label: &str) -> Option<&'static Encoding> {
+	Encoding::for_label(label.as_bytes())
+		// utf-16 gets written by hand, the rest must round trip through encoding_rs
+		.filter(|e| *e == encoding_rs::UTF_16LE || *e == encoding_rs::UTF_16BE || e.output_encoding() == *e)
+}
+
+/// a file as read from disk
+pub struct Loaded {
+	pub text: ropey::Rope,
+	pub stamp: Stamp,
+	pub format: Format,
+	/// doesnt look like text. it's decoded byte per char so nothing gets lost
+	pub binary: bool,
+}
+
+/// reads a whole file, along with its stamp and format.
+/// fallback is the 8 bit encoding used when the file isnt utf-8 or utf-16.
+pub fn read(path: &Path, fallback: &'static Encoding) -> io::Result<Loaded> {
+	let bytes = fs::read(path)?;
+	let stamp = Stamp::new(path, &bytes);
+	let (text, format, binary) = decode(&bytes, fallback);
+	Ok(Loaded { text, stamp, format, binary })
+}
+
+/// turns file bytes into editor text, guessing the encoding and
+/// normalizing line endings. returns whether the bytes look binary.
+fn decode(bytes: &[u8], fallback: &'static Encoding) -> (ropey::Rope, Format, bool) {
+	let (encoding, bom) = match Encoding::for_bom(bytes) {
+		Some((e, len)) => (e, len),
+		// ascii as utf-16 is valid utf-8 too, so this goes first
+		None => match sniff_utf16(bytes) {
+			Some(e) => (e, 0),
+			// nul bytes dont belong in 8 bit text
+			None if bytes.contains(&0) => return decode_binary(bytes),
+			None if std::str::from_utf8(bytes).is_ok() => (encoding_rs::UTF_8, 0),
+			None => (fallback, 0),
+		}
+	};
+	let (text, malformed) = encoding.decode_without_bom_handling(&bytes[bom..]);
+	if malformed || text.contains('\0') {
+		return decode_binary(bytes);
+	}
+
+	// lines end in \n inside the editor, whatever the file uses.
+	// a lone \r only counts as a line ending in mac files
+	let eol = LineEnding::detect(&text);
+	let text = match (eol, text.contains('\r')) {
+		(_, false) => ropey::Rope::from_str(&text),
+		(LineEnding::Mac, true) => ropey::Rope::from_str(&text.replace("\r\n", "\n").replace('\r', "\n")),
+		(_, true) => ropey::Rope::from_str(&text.replace("\r\n", "\n")),
+	};
+	(text, Format { eol, bom: bom > 0, encoding }, false)
+}
+
+/// every byte becomes one char and line endings stay as they are,
+/// so writing it back gives the same bytes
+fn decode_binary(bytes: &[u8]) -> (ropey::Rope, Format, bool) {
+	let encoding = encoding_rs::WINDOWS_1252;
+	let (text, _) = encoding.decode_without_bom_handling(bytes);
+	(ropey::Rope::from_str(&text), Format { encoding, ..Format::default() }, true)
+}
+
+/// utf-16 without a bom: mostly ascii text has a nul in every other byte
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+	let sample = &bytes[..bytes.len().min(4096) & !1];
+	if sample.is_empty() { return Option::None; }
+	let pairs = sample.len() / 2;
+	let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+	let odd = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+	if odd * 10 >= pairs * 7 && even == 0 {
+		Some(encoding_rs::UTF_16LE)
+	} else if even * 10 >= pairs * 7 && odd == 0 {
+		Some(encoding_rs::UTF_16BE)
+	} else {
+		Option::None
+	}
+}
+
+/// writes editor text in the given format, the reverse of decode
+pub fn encode(wr: &mut dyn io::Write, text: &ropey::Rope, format: Format) -> io::Result<()> {
+	let mut sink = Sink::new(wr, format.encoding);
+	if format.bom {
+		sink.put("\u{feff}")?;
+	}
+	for chunk in text.chunks() {
+		let mut lines = chunk.split('\n');
+		if let Some(first) = lines.next() {
+			sink.put(first)?;
+		}
+		for line in lines {
+			sink.put(format.eol.as_str())?;
+			sink.put(line)?;
+		}
+	}
+	sink.finish()
+}
+
+/// transcodes utf-8 text on its way to the writer
+struct Sink<'a> {
+	wr: &'a mut dyn io::Write,
+	encoding: &'static Encoding,
+	encoder: encoding_rs::Encoder,
+	buf: Vec<u8>,
+}
+
+impl<'a> Sink<'a> {
+	fn new(wr: &'a mut dyn io::Write, encoding: &'static Encoding) -> Self {
+		Sink { wr, encoding, encoder: encoding.new_encoder(), buf: vec![0; 4096] }
+	}
+
+	fn put(&mut self, s: &str) -> io::Result<()> {
+		if self.encoding == encoding_rs::UTF_8 {
+			return self.wr.write_all(s.as_bytes());
+		}
+		// encoding_rs only decodes utf-16, writing it is easy enough
+		if self.encoding == encoding_rs::UTF_16LE || self.encoding == encoding_rs::UTF_16BE {
+			let le = self.encoding == encoding_rs::UTF_16LE;
+			for unit in s.encode_utf16() {
+				let bytes = if le { unit.to_le_bytes() } else { unit.to_be_bytes() };
+				self.wr.write_all(&bytes)?;
+			}
+			return Ok(());
+		}
+		self.transcode(s, false)
+	}
+
+	fn finish(mut self) -> io::Result<()> {
+		if self.encoding == encoding_rs::UTF_8 || self.encoding.output_encoding() != self.encoding {
+			return Ok(());
+		}
+		self.transcode("", true)
+	}
+
+	fn transcode(&mut self, mut s: &str, last: bool) -> io::Result<()> {
+		use encoding_rs::EncoderResult;
+		loop {
+			let (res, read, written) = self.encoder
+				.encode_from_utf8_without_replacement(s, &mut self.buf, last);
+			self.wr.write_all(&self.buf[..written])?;
+			s = &s[read..];
+			match res {
+				EncoderResult::InputEmpty => return Ok(()),
+				EncoderResult::OutputFull => {},
+				EncoderResult::Unmappable(c) => return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("{:?} cant be written as {}", c, self.encoding.name())
+				)),
+			}
+		}
+	}
+}
+
+/// write_atomic, but also returns the stamp of what got written
+pub fn write_stamped<F>(path: &Path, backup: bool, write: F) -> io::Result<Stamp>
+where F: FnOnce(&mut dyn io::Write) -> io::Result<()> {
+	let mut h = Hasher::default();
+	let mut size = 0;
+	write_atomic(path, backup, |wr| {
+		let mut wr = HashWriter { inner: wr, hasher: &mut h, size: &mut size };
+		write(&mut wr)
+	})?;
+	Ok(Stamp {
+		mtime: fs::metadata(path).and_then(|m| m.modified()).ok(),
+		size,
+		hash: h.0,
+	})
+}
+
+/// hashes bytes the same way stamps do
+pub fn hash(bytes: &[u8]) -> u64 {
+	let mut h = Hasher::default();
+	h.update(bytes);
+	h.0
+}
+
+/// 64 bit fnv-1a, stable between runs unlike std's hasher
+struct Hasher(u64);
+
+impl Default for Hasher {
+	fn default() -> Self {
+		Hasher(0xcbf29ce484222325)
+	}
+}
+
+impl Hasher {
+	fn update(&mut self, bytes: &[u8]) {
+		for b in bytes {
+			self.0 ^= *b as u64;
+			self.0 = self.0.wrapping_mul(0x100000001b3);
+		}
+	}
+}
+
+/// hashes everything that goes through it
+struct HashWriter<'a> {
+	inner: &'a mut dyn io::Write,
+	hasher: &'a mut Hasher,
+	size: &'a mut u64,
+}
+
+impl io::Write for HashWriter<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let n = self.inner.write(buf)?;
+		self.hasher.update(&buf[..n]);
+		*self.size += n as u64;
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
+
+/// writes a file without ever leaving it half written.
+///
+/// the content goes to a temp file in the same directory, which gets synced
+/// and then renamed over the target. permissions (and ownership, if we are
+/// allowed to) of the old file are kept. if backup is set the old file
+/// survives as `path~`.
+pub fn write_atomic<F>(path: &Path, backup: bool, write: F) -> io::Result<()>
+where F: FnOnce(&mut dyn io::Write) -> io::Result<()> {
+	// writing through a symlink should replace the file, not the link
+	let path = match fs::symlink_metadata(path) {
+		Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)?,
+		_ => path.to_path_buf(),
+	};
+	let old = fs::metadata(&path).ok();
+	let tmp = tmp_path(&path);
+
+	if let Err(e) = write_tmp(&tmp, old.as_ref(), write) {
+		_ = fs::remove_file(&tmp);
+		return Err(e);
+	}
+	if backup && old.is_some() {
+		if let Err(e) = make_backup(&path) {
+			_ = fs::remove_file(&tmp);
+			return Err(e);
+		}
+	}
+	if let Err(e) = fs::rename(&tmp, &path) {
+		_ = fs::remove_file(&tmp);
+		return Err(e);
+	}
+	sync_dir(&path);
+	Ok(())
+}
+
+/// name of the backup file kept by write_atomic
+pub fn backup_path(path: &Path) -> PathBuf {
+	let mut name = path.as_os_str().to_owned();
+	name.push("~");
+	PathBuf::from(name)
+}
+
+/// temp file sitting next to the target, so the rename stays on one filesystem
+fn tmp_path(path: &Path) -> PathBuf {
+	let name = path.file_name()
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	path.with_file_name(format!(".{}.{}.xi-tmp", name, std::process::id()))
+}
+
+fn write_tmp<F>(tmp: &Path, old: Option<&fs::Metadata>, write: F) -> io::Result<()>
+where F: FnOnce(&mut dyn io::Write) -> io::Result<()> {
+	let file = fs::OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(tmp)?;
+	let mut wr = io::BufWriter::new(file);
+	write(&mut wr)?;
+	let file = wr.into_inner().map_err(|e| e.into_error())?;
+	file.sync_all()?;
+
+	if let Some(meta) = old {
+		fs::set_permissions(tmp, meta.permissions())?;
+		#[cfg(unix)]
+		{
+			// only root can give files away, so failing here is fine
+			use std::os::unix::fs::MetadataExt;
+			_ = std::os::unix::fs::chown(tmp, Some(meta.uid()), Some(meta.gid()));
+		}
+	}
+	Ok(())
+}
+
+/// hard links the old file to `path~`, falls back to copying it
+fn make_backup(path: &Path) -> io::Result<()> {
+	let backup = backup_path(path);
+	match fs::remove_file(&backup) {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+		_ => {}
+	}
+	if fs::hard_link(path, &backup).is_err() {
+		fs::copy(path, &backup)?;
+	}
+	Ok(())
+}
+
+/// true if a and b name the same file, eg `a.txt` and `./a.txt`
+pub fn same_file(a: &str, b: &str) -> bool {
+	match (fs::canonicalize(a), fs::canonicalize(b)) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => a == b,
+	}
+}
+
+/// makes the rename itself durable. not every platform can open
+/// directories, so errors are ignored
+fn sync_dir(path: &Path) {
+	let dir = match path.parent() {
+		Some(d) if !d.as_os_str().is_empty() => d,
+		_ => Path::new("."),
+	};
+	if let Ok(d) = fs::File::open(dir) {
+		_ = d.sync_all();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::scratch::Scratch;
+
+	#[test]
+	fn write_atomic_test() {
+		let dir = Scratch::new("file-atomic");
+		let path = dir.join("a.txt");
+		write_atomic(&path, false, |w| w.write_all(b"one")).unwrap();
+		assert_eq!("one", fs::read_to_string(&path).unwrap());
+		// a failing write keeps the old content and leaves no temp file behind
+		let res = write_atomic(&path, false, |w| {
+			w.write_all(b"half")?;
+			Err(io::Error::other("disk full"))
+		});
+		assert!(res.is_err());
+		assert_eq!("one", fs::read_to_string(&path).unwrap());
+		assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+	}
+
+	#[test]
+	fn backup_test() {
+		let dir = Scratch::new("file-backup");
+		let path = dir.join("b.txt");
+		write_atomic(&path, true, |w| w.write_all(b"old")).unwrap();
+		assert!(!backup_path(&path).exists(), "nothing to back up yet");
+		write_atomic(&path, true, |w| w.write_all(b"new")).unwrap();
+		assert_eq!("new", fs::read_to_string(&path).unwrap());
+		assert_eq!("old", fs::read_to_string(backup_path(&path)).unwrap());
+	}
+
+	#[test]
+	fn same_file_test() {
+		let dir = Scratch::new("file-same");
+		let a = dir.join("a.txt").to_string_lossy().into_owned();
+		fs::write(&a, "a").unwrap();
+		let dotted = dir.join(".").join("a.txt").to_string_lossy().into_owned();
+		assert!(same_file(&a, &dotted));
+		assert!(!same_file(&a, &dir.join("b.txt").to_string_lossy()));
+	}
+
+	#[test]
+	fn stamp_test() {
+		let dir = Scratch::new("file-stamp");
+		let path = dir.join("d.txt");
+		let stamp = write_stamped(&path, false, |w| w.write_all(b"same")).unwrap();
+		assert_eq!(stamp, Stamp::read(&path).unwrap());
+		assert!(!stamp.changed(&path));
+		// same content written again is not a change
+		fs::write(&path, "same").unwrap();
+		assert!(!stamp.changed(&path));
+		fs::write(&path, "other").unwrap();
+		assert!(stamp.changed(&path));
+	}
+
+	#[test]
+	fn line_ending_test() {
+		let (text, format, _) = decode(b"a\r\nb\r\n", encoding_rs::WINDOWS_1252);
+		assert_eq!("a\nb\n", text.to_string());
+		assert_eq!(LineEnding::Dos, format.eol);
+		let (text, format, _) = decode(b"a\rb", encoding_rs::WINDOWS_1252);
+		assert_eq!("a\nb", text.to_string());
+		assert_eq!(LineEnding::Mac, format.eol);
+		// mixed files lose every \r\n and keep what most lines use
+		let (text, format, _) = decode(b"a\r\nb\n", encoding_rs::WINDOWS_1252);
+		assert_eq!("a\nb\n", text.to_string());
+		assert_eq!(LineEnding::Unix, format.eol);
+		let (text, format, _) = decode(b"a\r\nb\r\nc\nd\r", encoding_rs::WINDOWS_1252);
+		assert_eq!("a\nb\nc\nd\r", text.to_string());
+		assert_eq!(LineEnding::Dos, format.eol);
+		let (text, format, _) = decode(b"a\rb\rc\r\n", encoding_rs::WINDOWS_1252);
+		assert_eq!("a\nb\nc\n", text.to_string());
+		assert_eq!(LineEnding::Mac, format.eol);
+	}
+
+	#[test]
+	fn round_trip_test() {
+		for bytes in [
+			&b"\xef\xbb\xbfa\r\n\r\nb"[..], b"a\rb\r", b"plain\n", b"",
+			b"caf\xe9\r\n", b"\xff\xfea\x00\r\x00\n\x00", b"\x00a\x00b", b"\x7fELF\x00\x01\r\n\xff"
+		] {
+			let (text, format, _) = decode(bytes, encoding_rs::WINDOWS_1252);
+			let mut out = vec![];
+			encode(&mut out, &text, format).unwrap();
+			assert_eq!(bytes, &out[..]);
+		}
+		let (text, format, _) = decode(b"\xef\xbb\xbfx", encoding_rs::WINDOWS_1252);
+		assert!(format.bom);
+		assert_eq!("x", text.to_string());
+	}
+
+	#[test]
+	fn encoding_test() {
+		let (text, format, binary) = decode(b"caf\xe9", encoding_rs::WINDOWS_1252);
+		assert_eq!("café", text.to_string());
+		assert_eq!(encoding_rs::WINDOWS_1252, format.encoding);
+		assert!(!binary);
+		let (text, format, _) = decode(b"h\x00i\x00\n\x00", encoding_rs::WINDOWS_1252);
+		assert_eq!("hi\n", text.to_string());
+		assert_eq!(encoding_rs::UTF_16LE, format.encoding);
+		assert!(!format.bom);
+		// some chars just dont exist in latin1
+		let mut out = vec![];
+		let format = Format { encoding: encoding_rs::WINDOWS_1252, ..Format::default() };
+		assert!(encode(&mut out, &ropey::Rope::from("€ ok"), format).is_ok());
+		assert!(encode(&mut out, &ropey::Rope::from("日本"), format).is_err());
+		assert_eq!(Some(encoding_rs::WINDOWS_1252), encoding_for("latin1"));
+		assert_eq!(None, encoding_for("replacement"));
+	}
+
+	#[test]
+	fn binary_test() {
+		let bytes = b"\x7fELF\x02\x01\x00\x00\r\n\xff";
+		let (text, _, binary) = decode(bytes, encoding_rs::WINDOWS_1252);
+		assert!(binary);
+		assert_eq!(bytes.len(), text.len_chars(), "one char per byte");
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn keeps_permissions_test() {
+		use std::os::unix::fs::PermissionsExt;
+		let dir = Scratch::new("file-perm");
+		let path = dir.join("c.sh");
+		fs::write(&path, "echo").unwrap();
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+		write_atomic(&path, false, |w| w.write_all(b"echo hi")).unwrap();
+		let mode = fs::metadata(&path).unwrap().permissions().mode();
+		assert_eq!(0o750, mode & 0o777);
+	}
+}
@@ -0,0 +1,172 @@
+/*
+* hex view - binary files are shown as a dump of 16 bytes per line:
+*
+*   7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  .ELF............
+*
+* the dump itself is the buffer text, so undo, swap files & co just work.
+* the bytes are parsed back out of the hex columns when writing.
+*/
+pub const ROW: usize = 16;
+/// where the ascii column starts
+const ASCII: usize = ROW * 3 + 2;
+
+/// column of the high nibble of the i-th byte of a row
+fn col(i: usize) -> usize {
+	i * 3 + (i >= ROW / 2) as usize
+}
+
+fn ascii(b: u8) -> char {
+	if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }
+}
+
+/// builds the dump of bytes
+pub fn dump(bytes: &[u8]) -> ropey::Rope {
+	let mut out = String::with_capacity(bytes.len() / ROW * (ASCII + ROW + 1) + ASCII + ROW);
+	for (n, row) in bytes.chunks(ROW).enumerate() {
+		if n > 0 { out.push('\n'); }
+		for i in 0..ROW {
+			if i == ROW / 2 { out.push(' '); }
+			match row.get(i) {
+				Some(b) => out.push_str(&format!("{:02x} ", b)),
+				None => out.push_str("   "),
+			}
+		}
+		out.push(' ');
+		out.extend(row.iter().map(|b| ascii(*b)));
+	}
+	ropey::Rope::from(out)
+}
+
+/// reads the bytes back from a dump
+pub fn parse(dump: &ropey::Rope) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(dump.len_lines() * ROW);
+	for line in dump.lines() {
+		for i in 0..ROW {
+			let Some(b) = byte_at(line, i) else { break; };
+			bytes.push(b);
+		}
+	}
+	bytes
+}
+
+fn byte_at(line: ropey::RopeSlice<'_>, i: usize) -> Option<u8> {
+	let hi = line.get_char(col(i))?.to_digit(16)?;
+	let lo = line.get_char(col(i) + 1)?.to_digit(16)?;
+	Some((hi * 16 + lo) as u8)
+}
+
+/// number of nibbles in the dump
+pub fn len(dump: &ropey::Rope) -> usize {
+	let rows = dump.len_lines();
+	let last = dump.line(rows - 1);
+	let bytes_last = (0..ROW).take_while(|i| byte_at(last, *i).is_some()).count();
+	((rows - 1) * ROW + bytes_last) * 2
+}
+
+/// rope index of a nibble
+pub fn nibble_to_char(dump: &ropey::Rope, nib: usize) -> usize {
+	let byte = nib / 2;
+	dump.line_to_char(byte / ROW) + col(byte % ROW) + nib % 2
+}
+
+/// nibble under (or closest to) a rope index.
+/// the ascii column maps to the high nibble of its byte.
+pub fn char_to_nibble(dump: &ropey::Rope, cs: usize) -> usize {
+	let row = dump.char_to_line(cs);
+	let x = cs - dump.line_to_char(row);
+	let (i, lo) = if x >= ASCII {
+		(x - ASCII, false)
+	} else {
+		let i = (0..ROW).rev().find(|i| col(*i) <= x).unwrap_or(0);
+		(i, x > col(i))
+	};
+	let nib = (row * ROW + i.min(ROW - 1)) * 2 + lo as usize;
+	nib.min(len(dump).saturating_sub(1))
+}
+
+/// the edits needed to set a nibble to digit:
+/// (rope index, new char) for the hex digit and the ascii column
+pub fn overwrite(dump: &ropey::Rope, nib: usize, digit: char) -> Option<[(usize, char); 2]> {
+	let d = digit.to_digit(16)? as u8;
+	let byte = nib / 2;
+	let (row, i) = (byte / ROW, byte % ROW);
+	let old = byte_at(dump.line(row), i)?;
+	let new = if nib.is_multiple_of(2) { (old & 0x0f) | d << 4 } else { (old & 0xf0) | d };
+	let start = dump.line_to_char(row);
+	Some([
+		(start + col(i) + nib % 2, digit.to_ascii_lowercase()),
+		(start + ASCII + i, ascii(new)),
+	])
+}
+
+/// parses a byte pattern like `7f454c46` or `7f 45 4c 46`
+pub fn parse_pattern(s: &str) -> Option<Vec<u8>> {
+	let digits: Vec<u32> = s.chars()
+		.filter(|c| !c.is_whitespace())
+		.map(|c| c.to_digit(16))
+		.collect::<Option<_>>()?;
+	if digits.is_empty() || !digits.len().is_multiple_of(2) { return Option::None; }
+	Some(digits.chunks(2).map(|p| (p[0] * 16 + p[1]) as u8).collect())
+}
+
+/// first match of needle starting after byte `from`, wrapping around
+pub fn find(bytes: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+	let hit = |i: &usize| bytes[*i..].starts_with(needle);
+	(from + 1..bytes.len()).find(hit)
+		.or_else(|| (0..=from.min(bytes.len().saturating_sub(1))).find(hit))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn dump_test() {
+		let d = dump(b"\x7fELF\x00 ok");
+		assert_eq!(
+			"7f 45 4c 46 00 20 6f 6b                           .ELF. ok",
+			d.to_string()
+		);
+		assert_eq!(16, len(&d));
+		let bytes: Vec<u8> = (0..=255).collect();
+		let d = dump(&bytes);
+		assert_eq!(16, d.len_lines());
+		assert_eq!(bytes, parse(&d));
+		assert!(parse(&dump(b"")).is_empty());
+	}
+
+	#[test]
+	fn nibble_test() {
+		let d = dump(&[0u8; 40]);
+		for nib in 0..80 {
+			assert_eq!(nib, char_to_nibble(&d, nibble_to_char(&d, nib)));
+		}
+		// the gap between the two halves
+		assert_eq!(8 * 2, char_to_nibble(&d, col(8) - 1) + 1);
+		// ascii column
+		assert_eq!(3 * 2, char_to_nibble(&d, ASCII + 3));
+	}
+
+	#[test]
+	fn overwrite_test() {
+		let mut d = dump(b"AB");
+		for (at, c) in overwrite(&d, 1, '2').unwrap() {
+			d.remove(at..at + 1);
+			d.insert_char(at, c);
+		}
+		assert_eq!(vec![0x42, 0x42], parse(&d));
+		assert!(d.to_string().ends_with("BB"));
+		assert!(overwrite(&d, 1, 'x').is_none());
+		assert!(overwrite(&d, 4, '1').is_none(), "past the end");
+	}
+
+	#[test]
+	fn find_test() {
+		assert_eq!(Some(vec![0xde, 0xad]), parse_pattern("de AD"));
+		assert_eq!(None, parse_pattern("dea"));
+		let bytes = b"abcabc";
+		assert_eq!(Some(3), find(bytes, b"abc", 0));
+		assert_eq!(Some(0), find(bytes, b"abc", 3), "wraps around");
+		assert_eq!(None, find(bytes, b"x", 0));
+	}
+}
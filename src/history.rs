@@ -1,135 +1,867 @@
-use crate::buffer::Move;
+/*
+* history - undo/redo as a tree of edits.
+*
+* an Edit is a group of changes that gets undone in one go. changes store
+* the text they removed and inserted, so undoing patches the rope instead
+* of keeping (and comparing) copies of it.
+*
+* this file only depends on ropey, benches/undo.rs includes it as is.
+*/
+use std::{io::{self, Read}, ops::Range, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
-/// history impl is timeline of saved (recorded) states (Edits).
-/// if the current state is to be recorded, it is added in the timeline
-/// the first item in the timeline should be the buffers state upon opening the file.
+/// what the history changes. usually a rope, or something around one that
+/// wants to hear about every change (the syntax tree does)
+pub trait Text {
+	/// removed, at char at, gets replaced by inserted
+	fn replace(&mut self, at: usize, removed: &str, inserted: &str);
+}
+
+impl Text for ropey::Rope {
+	fn replace(&mut self, at: usize, removed: &str, inserted: &str) {
+		self.remove(at..at + removed.chars().count());
+		self.insert(at, inserted);
+	}
+}
+
+/// a single change: `removed` was replaced by `inserted` at char index `at`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Change {
+	pub at: usize,
+	pub removed: String,
+	pub inserted: String,
+}
+
+impl Change {
+	pub fn insert(at: usize, text: impl Into<String>) -> Self {
+		Change { at, removed: String::new(), inserted: text.into() }
+	}
+
+	pub fn remove(at: usize, text: impl Into<String>) -> Self {
+		Change { at, removed: text.into(), inserted: String::new() }
+	}
+
+	/// the change that turns old into new, only the part in the middle
+	/// that differs is in it
+	pub fn between(old: &str, new: &str) -> Self {
+		let (at, prefix) = old.chars().zip(new.chars())
+			.take_while(|(a, b)| a == b)
+			.fold((0, 0), |(n, len), (c, _)| (n + 1, len + c.len_utf8()));
+		let (old, new) = (&old[prefix..], &new[prefix..]);
+		let suffix: usize = old.chars().rev().zip(new.chars().rev())
+			.take_while(|(a, b)| a == b)
+			.map(|(a, _)| a.len_utf8())
+			.sum();
+		Change {
+			at,
+			removed: old[..old.len() - suffix].to_owned(),
+			inserted: new[..new.len() - suffix].to_owned(),
+		}
+	}
+
+	fn apply(&self, text: &mut impl Text) {
+		text.replace(self.at, &self.removed, &self.inserted);
+	}
+
+	fn revert(&self, text: &mut impl Text) {
+		text.replace(self.at, &self.inserted, &self.removed);
+	}
+
+	/// folds next into self when they touch, so typing a word is one change
+	fn merge(&mut self, next: &Change) -> bool {
+		let pure_insert = |c: &Change| c.removed.is_empty();
+		let pure_remove = |c: &Change| c.inserted.is_empty();
+
+		if pure_insert(self) && pure_insert(next)
+			&& self.at + self.inserted.chars().count() == next.at {
+			self.inserted.push_str(&next.inserted);
+		} else if pure_remove(self) && pure_remove(next) && next.at == self.at {
+			// delete key
+			self.removed.push_str(&next.removed);
+		} else if pure_remove(self) && pure_remove(next)
+			&& next.at + next.removed.chars().count() == self.at {
+			// backspace
+			self.removed.insert_str(0, &next.removed);
+			self.at = next.at;
+		} else {
+			return false;
+		}
+		true
+	}
+}
+
+/// where the user was, so undo/redo can put them back there
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cursor {
+	pub cs: usize,
+	/// anchor and end of the selection, if there was one
+	pub selection: Option<(usize, usize)>,
+	/// first visual line on screen
+	pub offset: usize,
+}
+
+impl From<usize> for Cursor {
+	fn from(cs: usize) -> Self {
+		Cursor { cs, ..Default::default() }
+	}
+}
+
+/// changes that are undone/redone together, with the cursor around them
+#[derive(Clone, Debug, Default)]
+pub struct Edit {
+	pub changes: Vec<Change>,
+	pub before: Cursor,
+	pub after: Cursor,
+}
+
+impl Edit {
+	/// the text touched by the changes, once they are applied (or reverted)
+	fn changed(&self, undone: bool) -> Range<usize> {
+		let len = |c: &Change| match undone {
+			true => c.removed.chars().count(),
+			false => c.inserted.chars().count(),
+		};
+		let start = self.changes.iter().map(|c| c.at).min().unwrap_or(0);
+		let end = self.changes.iter().map(|c| c.at + len(c)).max().unwrap_or(0);
+		start..end
+	}
+}
+
+/// where undo/redo left things: the cursor to restore and what changed
+#[derive(Debug, PartialEq, Eq)]
+pub struct Landing {
+	pub cursor: Cursor,
+	pub changed: Range<usize>,
+}
+
+/// a state of the text, reached by applying edit to the parent state
+struct Node {
+	edit: Edit,
+	parent: usize,
+	/// the child redo goes to, the one we last came back from
+	next: Option<usize>,
+	time: SystemTime,
+}
+
+/// a state as shown in listings
+pub struct State {
+	pub seq: usize,
+	/// how many edits away from the opened file
+	pub changes: usize,
+	pub time: SystemTime,
+}
+
+/// the history is a tree of states, undoing and then typing starts a new
+/// branch instead of throwing the undone edits away.
+/// states are numbered in the order they were made, 0 is the opened file.
+/// new changes collect in pending until stashed.
 pub struct History {
-    timeline	: Vec<Edit>,
-    curr        : usize,
-    saved       : usize,
-    dirty       : bool,
+	nodes		: Vec<Node>,
+	curr		: usize,
+	saved		: usize,
+	/// states that were written, oldest first
+	writes		: Vec<usize>,
+	pending		: Edit,
+	/// when the last change was recorded
+	touched		: Option<Instant>,
+	/// open begin_groups, the pending edit stays open while > 0
+	grouping	: usize,
+}
+
+/// what makes a pending edit into an undo step
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Group {
+	/// every word typed or deleted, and every cursor move
+	#[default]
+	Word,
+	/// everything typed until leaving insert mode
+	Insert,
+	/// typing until it stops for a while
+	Idle(Duration),
+}
+
+impl Group {
+	/// `word`, `insert`, `idle` or `idle:<ms>`
+	pub fn from_name(name: &str) -> Option<Group> {
+		match name.split_once(':') {
+			Option::None => match name {
+				"word" => Some(Group::Word),
+				"insert" => Some(Group::Insert),
+				"idle" => Some(Group::Idle(Duration::from_secs(1))),
+				_ => Option::None,
+			},
+			Some(("idle", ms)) => ms.parse().ok().map(|ms| Group::Idle(Duration::from_millis(ms))),
+			_ => Option::None,
+		}
+	}
+}
+
+/// how far `:earlier`/`:later` go
+#[derive(Debug, PartialEq, Eq)]
+pub enum Span {
+	Steps(usize),
+	Time(Duration),
+	/// file writes
+	Writes(usize),
+}
+
+impl Span {
+	/// parses `3` (steps), `10s`, `5m`, `2h`, `1d` or `3f`
+	pub fn parse(s: &str) -> Option<Span> {
+		let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+		let (n, unit) = s.split_at(split);
+		let n: u64 = n.parse().ok()?;
+		let secs = |mul| n.checked_mul(mul).map(|s| Span::Time(Duration::from_secs(s)));
+		match unit {
+			"" => Some(Span::Steps(n as usize)),
+			"s" => secs(1),
+			"m" => secs(60),
+			"h" => secs(3600),
+			"d" => secs(86400),
+			"f" => Some(Span::Writes(n as usize)),
+			_ => Option::None,
+		}
+	}
 }
 
 impl History {
-    /// updates the timeline
-    pub fn update<E: EditAction>(&mut self, ea: &E, ctx: &ropey::Rope, cs: usize) {
-        if ea.stains() { self.dirty = true; }
-        if ea.should_stash() { self.stash(ctx, cs); }
-        self.timeline.truncate(self.curr +1);
-    }
-
-    /// stashe a new edit, if meaningfull
-    pub fn stash(&mut self, ctx: &ropey::Rope, cs: usize){
-        if !ctx.eq(&self.timeline[self.curr].text) {
-            // trying this idea
-            self.timeline.push(Edit::from(ctx.clone(), cs));
-            self.curr += 1;
-            self.dirty = false;
-        }
-    }
-
-    /// updates the timeline.
-    /// caller should update its contents based on returned new current edit
-    pub fn undo(&mut self) -> &Edit {
-        self.curr = self.curr.saturating_sub(1);
-        &self.timeline[self.curr]
-    }
-
-    /// similar to undo.
-    pub fn redo(&mut self) -> Option<&Edit> {
-        if self.dirty { return Option::None; }
-
-        self.curr = (self.timeline.len() - 1).min(self.curr + 1);
-        Some(&self.timeline[self.curr])
-    }
-
-    /// returns whether this history is at a saved spot or nah
-    pub fn is_dirty(&self) -> bool {
-        self.curr != self.saved || self.dirty
-    }
-
-    /// registers a save in the history
-    pub fn save(&mut self) {
-        self.saved = self.curr;
-		self.dirty = false;
-    }
+	/// lets the action decide if the pending edit is over, following
+	/// the group policy. inside begin_group/end_group nothing is over.
+	pub fn update<E: EditAction>(&mut self, ea: &E, group: Group) {
+		if self.grouping > 0 { return; }
+		let idle = match group {
+			Group::Idle(d) => self.touched.is_some_and(|t| t.elapsed() >= d),
+			_ => false,
+		};
+		if idle || ea.should_stash(group) { self.stash(); }
+	}
+
+	/// starts an edit that is undone in one step, whatever the policy,
+	/// until the matching end_group. groups can nest.
+	pub fn begin_group(&mut self) {
+		if self.grouping == 0 { self.close(); }
+		self.grouping += 1;
+	}
+
+	pub fn end_group(&mut self) {
+		self.grouping = self.grouping.saturating_sub(1);
+		if self.grouping == 0 { self.close(); }
+	}
+
+	/// records a change that was just made to the text
+	pub fn record(&mut self, change: Change, before: impl Into<Cursor>, after: impl Into<Cursor>) {
+		self.touched = Some(Instant::now());
+		if self.pending.changes.is_empty() {
+			self.pending.before = before.into();
+		}
+		self.pending.after = after.into();
+		let merged = self.pending.changes.last_mut()
+			.is_some_and(|last| last.merge(&change));
+		if !merged {
+			self.pending.changes.push(change);
+		}
+	}
+
+	/// closes the pending edit and adds it as a new state, if there is one.
+	/// inside a group it waits for end_group.
+	pub fn stash(&mut self) {
+		if self.grouping > 0 { return; }
+		self.close();
+	}
+
+	/// adds the pending edit as a new state, group or not. for moving
+	/// around the history, which needs every change to be in a state
+	fn close(&mut self) {
+		if self.pending.changes.is_empty() { return; }
+
+		let seq = self.nodes.len();
+		self.nodes.push(Node {
+			edit: std::mem::take(&mut self.pending),
+			parent: self.curr,
+			next: Option::None,
+			time: SystemTime::now(),
+		});
+		self.nodes[self.curr].next = Some(seq);
+		self.curr = seq;
+	}
+
+	/// reverts the last edit on rope.
+	/// returns where the cursor should go, None if there's nothing to undo.
+	pub fn undo(&mut self, rope: &mut impl Text) -> Option<Landing> {
+		self.close();
+		if self.curr == 0 { return Option::None; }
+
+		let node = &self.nodes[self.curr];
+		for change in node.edit.changes.iter().rev() {
+			change.revert(rope);
+		}
+		let landing = Landing { cursor: node.edit.before, changed: node.edit.changed(true) };
+		let parent = node.parent;
+		self.nodes[parent].next = Some(self.curr);
+		self.curr = parent;
+		Some(landing)
+	}
+
+	/// similar to undo, follows the branch we last came from.
+	pub fn redo(&mut self, rope: &mut impl Text) -> Option<Landing> {
+		if !self.pending.changes.is_empty() { return Option::None; }
+		let next = self.nodes[self.curr].next?;
+
+		let edit = &self.nodes[next].edit;
+		for change in &edit.changes {
+			change.apply(rope);
+		}
+		self.curr = next;
+		Some(Landing { cursor: edit.after, changed: edit.changed(false) })
+	}
+
+	/// moves to state seq, on whatever branch it is: undoes up to the
+	/// common ancestor and redoes down from there.
+	/// the changed range starts at the first change of any step.
+	/// returns where the cursor should go, None if seq doesnt exist.
+	pub fn goto(&mut self, seq: usize, rope: &mut impl Text) -> Option<Landing> {
+		self.close();
+		if seq >= self.nodes.len() || seq == self.curr { return Option::None; }
+
+		// seq and its ancestors, seq first
+		let mut path = vec![seq];
+		while let Some(&n) = path.last().filter(|n| **n != 0) {
+			path.push(self.nodes[n].parent);
+		}
+		let mut landing: Option<Landing> = Option::None;
+		// nothing before the first change of any step was touched
+		let mut first = usize::MAX;
+		while !path.contains(&self.curr) {
+			landing = self.undo(rope);
+			first = first.min(landing.as_ref().map_or(first, |l| l.changed.start));
+		}
+		let down = path.iter().position(|n| *n == self.curr).unwrap_or(0);
+		for &n in path[..down].iter().rev() {
+			self.nodes[self.curr].next = Some(n);
+			landing = self.redo(rope);
+			first = first.min(landing.as_ref().map_or(first, |l| l.changed.start));
+		}
+		landing.map(|l| Landing { changed: first..l.changed.end.max(first), ..l })
+	}
+
+	/// number of the current state
+	pub fn seq(&self) -> usize {
+		self.curr
+	}
+
+	/// number of states, counting the opened file
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
 
 	#[cfg(test)]
 	pub fn tl_len(&self) -> usize {
-		self.timeline.len()
+		self.len()
+	}
+
+	/// the ends of every branch, oldest first
+	pub fn leaves(&self) -> Vec<State> {
+		let mut leaf = vec![true; self.nodes.len()];
+		for node in &self.nodes[1..] {
+			leaf[node.parent] = false;
+		}
+		(1..self.nodes.len())
+			.filter(|n| leaf[*n])
+			.map(|n| self.state(n))
+			.collect()
+	}
+
+	pub fn state(&self, seq: usize) -> State {
+		let mut changes = 0;
+		let mut n = seq;
+		while n != 0 {
+			n = self.nodes[n].parent;
+			changes += 1;
+		}
+		State { seq, changes, time: self.nodes[seq].time }
+	}
+
+	/// every state with its indentation for drawing the tree, top to bottom.
+	/// the newest branch goes straight on, older ones are indented above it.
+	pub fn layout(&self) -> Vec<(usize, usize)> {
+		let mut children = vec![vec![]; self.nodes.len()];
+		for (n, node) in self.nodes.iter().enumerate().skip(1) {
+			children[node.parent].push(n);
+		}
+		let mut rows = Vec::with_capacity(self.nodes.len());
+		let mut stack = vec![(0, 0)];
+		while let Some((n, depth)) = stack.pop() {
+			rows.push((n, depth));
+			if let Some((newest, older)) = children[n].split_last() {
+				stack.push((*newest, depth));
+				stack.extend(older.iter().rev().map(|c| (*c, depth + 1)));
+			}
+		}
+		rows
+	}
+
+	/// returns whether this history is at a saved spot or nah
+	pub fn is_dirty(&self) -> bool {
+		self.saved != self.curr || !self.pending.changes.is_empty()
+	}
+
+	/// registers a save in the history
+	pub fn save(&mut self) {
+		self.close();
+		self.saved = self.curr;
+		if self.writes.last() != Some(&self.curr) {
+			self.writes.push(self.curr);
+		}
+	}
+
+	/// the state that was last written
+	pub fn saved(&self) -> usize {
+		self.saved
+	}
+
+	/// the state `:earlier span` goes to, counting in the order states
+	/// were made (so across branches)
+	pub fn earlier(&mut self, span: Span) -> usize {
+		self.close();
+		let curr = self.curr;
+		match span {
+			Span::Steps(n) => curr.saturating_sub(n),
+			Span::Time(d) => {
+				let Some(when) = self.nodes[curr].time.checked_sub(d) else { return 0; };
+				(0..curr).rev().find(|n| self.nodes[*n].time <= when).unwrap_or(0)
+			},
+			// with changes since the last write, 1f goes back to that write
+			Span::Writes(n) => self.writes.iter().rev()
+				.filter(|w| **w < curr)
+				.nth(n.saturating_sub(1))
+				.copied()
+				.unwrap_or(0),
+		}
+	}
+
+	/// the state `:later span` goes to, the opposite of earlier
+	pub fn later(&mut self, span: Span) -> usize {
+		self.close();
+		let (curr, last) = (self.curr, self.nodes.len() - 1);
+		match span {
+			Span::Steps(n) => curr.saturating_add(n).min(last),
+			Span::Time(d) => {
+				// further than time goes is just the newest
+				let Some(when) = self.nodes[curr].time.checked_add(d) else { return last; };
+				(curr..=last).rev().find(|n| self.nodes[*n].time <= when).unwrap_or(curr)
+			},
+			Span::Writes(n) => self.writes.iter()
+				.filter(|w| **w > curr)
+				.nth(n.saturating_sub(1))
+				.copied()
+				.unwrap_or(last),
+		}
+	}
+}
+
+/*
+* (de)serialization for undo files. everything is a little endian u64 or a
+* string with its byte length in front.
+*/
+impl History {
+	/// writes the states to wr, the pending edit is left out
+	pub fn write_to(&self, wr: &mut dyn io::Write) -> io::Result<()> {
+		put(wr, self.saved as u64)?;
+		put(wr, self.writes.len() as u64)?;
+		for w in &self.writes {
+			put(wr, *w as u64)?;
+		}
+		put(wr, self.nodes.len() as u64)?;
+		for node in &self.nodes {
+			let time = node.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+			put(wr, node.parent as u64)?;
+			put(wr, node.next.map_or(u64::MAX, |n| n as u64))?;
+			put(wr, time.as_secs())?;
+			put(wr, time.subsec_nanos() as u64)?;
+			put_cursor(wr, &node.edit.before)?;
+			put_cursor(wr, &node.edit.after)?;
+			put(wr, node.edit.changes.len() as u64)?;
+			for change in &node.edit.changes {
+				put(wr, change.at as u64)?;
+				put_str(wr, &change.removed)?;
+				put_str(wr, &change.inserted)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// reads what write_to wrote. the text is expected to be the saved
+	/// state, thats where the history starts.
+	pub fn read_from(rd: &mut dyn io::Read) -> io::Result<History> {
+		let bad = || io::Error::new(io::ErrorKind::InvalidData, "broken undo history");
+		let saved = get(rd)? as usize;
+		let writes = (0..get(rd)?)
+			.map(|_| get(rd).map(|w| w as usize))
+			.collect::<io::Result<Vec<_>>>()?;
+		let len = get(rd)? as usize;
+		let mut nodes = Vec::new();
+		for n in 0..len {
+			let parent = get(rd)? as usize;
+			let next = match get(rd)? {
+				u64::MAX => Option::None,
+				next => Some(next as usize),
+			};
+			let (secs, nanos) = (get(rd)?, get(rd)?);
+			let time = match nanos {
+				0..1_000_000_000 => UNIX_EPOCH.checked_add(Duration::new(secs, nanos as u32)),
+				_ => Option::None,
+			}.ok_or_else(bad)?;
+			let (before, after) = (get_cursor(rd)?, get_cursor(rd)?);
+			let changes = (0..get(rd)?)
+				.map(|_| Ok(Change { at: get(rd)? as usize, removed: get_str(rd)?, inserted: get_str(rd)? }))
+				.collect::<io::Result<Vec<_>>>()?;
+			// parents always come first
+			if (n > 0 && parent >= n) || next.is_some_and(|next| next >= len) {
+				return Err(bad());
+			}
+			nodes.push(Node { edit: Edit { changes, before, after }, parent, next, time });
+		}
+		if nodes.is_empty() || saved >= len || writes.iter().any(|w| *w >= len) {
+			return Err(bad());
+		}
+		Ok(History { nodes, curr: saved, saved, writes, ..Default::default() })
 	}
 }
 
+fn put(wr: &mut dyn io::Write, n: u64) -> io::Result<()> {
+	wr.write_all(&n.to_le_bytes())
+}
+
+fn put_str(wr: &mut dyn io::Write, s: &str) -> io::Result<()> {
+	put(wr, s.len() as u64)?;
+	wr.write_all(s.as_bytes())
+}
+
+fn put_cursor(wr: &mut dyn io::Write, c: &Cursor) -> io::Result<()> {
+	put(wr, c.cs as u64)?;
+	let (anchor, end) = c.selection.map_or((u64::MAX, 0), |(a, e)| (a as u64, e as u64));
+	put(wr, anchor)?;
+	put(wr, end)?;
+	put(wr, c.offset as u64)
+}
+
+fn get_cursor(rd: &mut dyn io::Read) -> io::Result<Cursor> {
+	let cs = get(rd)? as usize;
+	let selection = match (get(rd)?, get(rd)?) {
+		(u64::MAX, _) => Option::None,
+		(a, e) => Some((a as usize, e as usize)),
+	};
+	Ok(Cursor { cs, selection, offset: get(rd)? as usize })
+}
+
+fn get(rd: &mut dyn io::Read) -> io::Result<u64> {
+	let mut buf = [0; 8];
+	rd.read_exact(&mut buf)?;
+	Ok(u64::from_le_bytes(buf))
+}
+
+fn get_str(rd: &mut dyn io::Read) -> io::Result<String> {
+	let len = get(rd)?;
+	let mut s = String::new();
+	rd.take(len).read_to_string(&mut s)?;
+	if s.len() as u64 != len {
+		return Err(io::ErrorKind::UnexpectedEof.into());
+	}
+	Ok(s)
+}
+
 impl Default for History {
-    fn default() -> Self {
-        Self {
-            timeline: vec![Edit::default()],
-            curr: 0,
-            saved: 0,
-            dirty: false,
-        }
-    }
-}
-
-impl From<ropey::Rope> for History {
-	fn from(initial: ropey::Rope) -> Self {
+	fn default() -> Self {
 		Self {
-            timeline: vec![Edit { text: initial, cs: 0}],
-            curr: 0,
-            saved: 0,
-            dirty: false,
-        }
+			nodes: vec![Node {
+				edit: Edit::default(),
+				parent: 0,
+				next: Option::None,
+				time: SystemTime::now(),
+			}],
+			curr: 0,
+			saved: 0,
+			writes: vec![],
+			pending: Edit::default(),
+			touched: Option::None,
+			grouping: 0,
+		}
 	}
 }
 
-pub trait EditAction {
-    fn should_stash(&self) -> bool;
+/// how long ago t was, like `12s` or `3h`
+pub fn ago(t: SystemTime) -> String {
+	let secs = t.elapsed().map(|d| d.as_secs()).unwrap_or(0);
+	match secs {
+		0..60 => format!("{}s", secs),
+		60..3600 => format!("{}m", secs / 60),
+		3600..86400 => format!("{}h", secs / 3600),
+		_ => format!("{}d", secs / 86400),
+	}
+}
 
-	fn stains(&self) -> bool;
+/// something done to the buffer, that might end the pending edit
+pub trait EditAction {
+	fn should_stash(&self, group: Group) -> bool;
 }
 
+/// a char typed or deleted
 impl EditAction for char {
-    fn should_stash(&self) -> bool {
-        self.is_whitespace()
-    }
-
-	fn stains(&self) -> bool {
-		true
+	fn should_stash(&self, group: Group) -> bool {
+		group == Group::Word && self.is_whitespace()
 	}
 }
 
+/// true always ends the edit
 impl EditAction for bool {
-    fn should_stash(&self) -> bool {
-        *self
-    }
-
-	fn stains(&self) -> bool {
+	fn should_stash(&self, _group: Group) -> bool {
 		*self
 	}
 }
 
-impl EditAction for Move {
-	fn should_stash(&self) -> bool {
-		true
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// types text at the end of rope, one char at a time
+	fn type_str(h: &mut History, rope: &mut ropey::Rope, text: &str) {
+		for c in text.chars() {
+			h.update(&c, Group::Word);
+			let at = rope.len_chars();
+			rope.insert_char(at, c);
+			h.record(Change::insert(at, c), at, at + 1);
+		}
 	}
 
-	fn stains(&self) -> bool {
-		false
+	fn cs(landing: Option<Landing>) -> Option<usize> {
+		landing.map(|l| l.cursor.cs)
 	}
-}
 
-#[derive(Default, Clone)]
-pub struct Edit {
-    pub text: ropey::Rope,
-    pub cs  : usize,
-}
+	#[test]
+	fn undo_redo_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::from("abc");
+		type_str(&mut h, &mut rope, "de fg");
+		assert_eq!(Some(5), cs(h.undo(&mut rope)));
+		assert_eq!("abcde", rope.to_string());
+		assert_eq!(Some(3), cs(h.undo(&mut rope)));
+		assert_eq!("abc", rope.to_string());
+		assert_eq!(None, h.undo(&mut rope));
+		assert_eq!(Some(5), cs(h.redo(&mut rope)));
+		assert_eq!(Some(8), cs(h.redo(&mut rope)));
+		assert_eq!("abcde fg", rope.to_string());
+		assert_eq!(None, h.redo(&mut rope));
+	}
 
-impl Edit {
-    fn from(text: ropey::Rope, cs: usize) -> Self {
-        Edit { 
-            text,
-            cs
-        }
-    }
-}
+	#[test]
+	fn merge_test() {
+		let mut c = Change::insert(2, "ab");
+		assert!(c.merge(&Change::insert(4, "c")));
+		assert!(!c.merge(&Change::insert(1, "x")));
+		assert_eq!("abc", c.inserted);
+		let mut c = Change::remove(5, "x");
+		assert!(c.merge(&Change::remove(4, "y")));
+		assert!(c.merge(&Change::remove(4, "z")));
+		assert_eq!((4, "yxz"), (c.at, c.removed.as_str()));
+	}
+
+	#[test]
+	fn replace_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::from("hello world");
+		let change = Change { at: 6, removed: "world".into(), inserted: "there".into() };
+		change.apply(&mut rope);
+		h.record(change, 6, 11);
+		assert_eq!("hello there", rope.to_string());
+		h.undo(&mut rope);
+		assert_eq!("hello world", rope.to_string());
+	}
+
+	#[test]
+	fn saved_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::new();
+		assert!(!h.is_dirty());
+		type_str(&mut h, &mut rope, "a ");
+		h.save();
+		assert!(!h.is_dirty());
+		h.undo(&mut rope);
+		assert!(h.is_dirty());
+		// a new edit branches off, the saved state is still reachable
+		type_str(&mut h, &mut rope, "b");
+		h.stash();
+		assert!(h.is_dirty());
+		h.goto(h.saved(), &mut rope);
+		assert!(!h.is_dirty());
+		assert_eq!("a ", rope.to_string());
+	}
+
+	#[test]
+	fn branch_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::new();
+		type_str(&mut h, &mut rope, "one two");
+		h.undo(&mut rope);
+		type_str(&mut h, &mut rope, " three");
+		h.stash();
+		assert_eq!("one three", rope.to_string());
+		assert_eq!(4, h.len());
+
+		// redo follows the branch we came back from
+		h.undo(&mut rope);
+		assert_eq!(Some(9), cs(h.redo(&mut rope)));
+		assert_eq!(3, h.seq());
+
+		// two and three are both leaves, both reachable
+		let leaves: Vec<usize> = h.leaves().iter().map(|s| s.seq).collect();
+		assert_eq!(vec![2, 3], leaves);
+		assert_eq!(2, h.state(2).changes);
+		h.goto(2, &mut rope);
+		assert_eq!("one two", rope.to_string());
+		h.goto(0, &mut rope);
+		assert_eq!("", rope.to_string());
+		h.goto(3, &mut rope);
+		assert_eq!("one three", rope.to_string());
+		assert_eq!(None, h.goto(3, &mut rope));
+		assert_eq!(None, h.goto(9, &mut rope));
+	}
+
+	#[test]
+	fn span_test() {
+		assert_eq!(Some(Span::Steps(3)), Span::parse("3"));
+		assert_eq!(Some(Span::Time(Duration::from_secs(600))), Span::parse("10m"));
+		assert_eq!(Some(Span::Writes(2)), Span::parse("2f"));
+		assert_eq!(None, Span::parse("m"));
+		assert_eq!(None, Span::parse("3y"));
+		assert_eq!(None, Span::parse("18446744073709551615m"), "overflows");
+	}
+
+	#[test]
+	fn earlier_later_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::new();
+		for (i, word) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+			type_str(&mut h, &mut rope, word);
+			h.stash();
+			if i % 2 == 1 { h.save(); }
+		}
+		assert_eq!(5, h.seq());
+		assert_eq!(3, h.earlier(Span::Steps(2)));
+		assert_eq!(4, h.earlier(Span::Writes(1)), "changes since the write");
+		assert_eq!(2, h.earlier(Span::Writes(2)));
+		assert_eq!(0, h.earlier(Span::Writes(3)));
+		assert_eq!(5, h.later(Span::Writes(1)), "no writes after, newest");
+		assert_eq!(5, h.later(Span::Steps(usize::MAX)));
 
+		h.goto(2, &mut rope);
+		assert_eq!(0, h.earlier(Span::Writes(1)), "at a write, goes to the one before");
+		assert_eq!(4, h.later(Span::Writes(1)));
+
+		// everything happened just now
+		assert_eq!(0, h.earlier(Span::Time(Duration::from_secs(60))));
+		assert_eq!(5, h.later(Span::Time(Duration::from_secs(60))));
+		assert_eq!(5, h.later(Span::Time(Duration::from_secs(u64::MAX))));
+	}
+
+	#[test]
+	fn write_read_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::new();
+		type_str(&mut h, &mut rope, "one two");
+		h.undo(&mut rope);
+		type_str(&mut h, &mut rope, " tres");
+		h.save();
+
+		let mut bytes = vec![];
+		h.write_to(&mut bytes).unwrap();
+		let mut r = History::read_from(&mut bytes.as_slice()).unwrap();
+		assert_eq!(h.layout(), r.layout());
+		assert!(!r.is_dirty());
+		r.undo(&mut rope);
+		assert_eq!("one", rope.to_string());
+		r.goto(2, &mut rope);
+		assert_eq!("one two", rope.to_string());
+
+		assert!(History::read_from(&mut &bytes[..bytes.len() - 3]).is_err());
+
+		// times that dont fit are a broken file, not a panic
+		let mut bytes = vec![];
+		History::default().write_to(&mut bytes).unwrap();
+		assert!(History::read_from(&mut bytes.as_slice()).is_ok());
+		let mut far = bytes.clone();
+		far[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+		assert!(History::read_from(&mut far.as_slice()).is_err());
+		let mut nanos = bytes.clone();
+		nanos[48..56].copy_from_slice(&1_000_000_000u64.to_le_bytes());
+		assert!(History::read_from(&mut nanos.as_slice()).is_err());
+	}
+
+	#[test]
+	fn landing_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::from("hello world");
+		let before = Cursor { cs: 6, selection: Some((6, 11)), offset: 3 };
+		let change = Change { at: 6, removed: "world".into(), inserted: "you".into() };
+		change.apply(&mut rope);
+		h.record(change, before, 9);
+		assert_eq!(Some(Landing { cursor: before, changed: 6..11 }), h.undo(&mut rope));
+		assert_eq!(Some(Landing { cursor: 9.into(), changed: 6..9 }), h.redo(&mut rope));
+	}
+
+	#[test]
+	fn group_test() {
+		assert_eq!(Some(Group::Insert), Group::from_name("insert"));
+		assert_eq!(Some(Group::Idle(Duration::from_millis(300))), Group::from_name("idle:300"));
+		assert_eq!(None, Group::from_name("idle:soon"));
+		assert_eq!(None, Group::from_name("line"));
+
+		let mut h = History::default();
+		let mut rope = ropey::Rope::new();
+		h.begin_group();
+		type_str(&mut h, &mut rope, "one two");
+		h.begin_group();
+		type_str(&mut h, &mut rope, " three");
+		h.end_group();
+		// a cursor move in the middle doesnt end it either
+		h.stash();
+		type_str(&mut h, &mut rope, " four");
+		h.end_group();
+		assert_eq!(2, h.len(), "one step");
+
+		for c in "a b".chars() {
+			h.update(&c, Group::Insert);
+			let at = rope.len_chars();
+			rope.insert_char(at, c);
+			h.record(Change::insert(at, c), at, at + 1);
+		}
+		h.undo(&mut rope);
+		assert_eq!("one two three four", rope.to_string());
+
+		h.update(&'x', Group::Idle(Duration::ZERO));
+		rope.insert_char(0, 'x');
+		h.record(Change::insert(0, 'x'), 0, 1);
+		h.update(&'y', Group::Idle(Duration::ZERO));
+		assert_eq!(Some(0), cs(h.undo(&mut rope)), "went idle after x");
+		assert_eq!("one two three four", rope.to_string());
+	}
+
+	#[test]
+	fn between_test() {
+		assert_eq!(Change { at: 2, removed: "c".into(), inserted: "xy".into() }, Change::between("abcd", "abxyd"));
+		assert_eq!(Change::insert(3, "é"), Change::between("aéb", "aébé"));
+		assert_eq!(Change::remove(2, "ab"), Change::between("abab", "ab"));
+		assert_eq!(Change::insert(4, ""), Change::between("same", "same"));
+	}
+
+	#[test]
+	fn layout_test() {
+		let mut h = History::default();
+		let mut rope = ropey::Rope::new();
+		type_str(&mut h, &mut rope, "a b");
+		h.undo(&mut rope);
+		type_str(&mut h, &mut rope, "c");
+		h.stash();
+		// 0 - 1 - 2
+		//       \ 3
+		assert_eq!(vec![(0, 0), (1, 0), (2, 1), (3, 0)], h.layout());
+	}
+}
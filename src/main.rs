@@ -9,12 +9,26 @@ mod selection;
 mod command;
 mod render;
 mod history;
+mod file;
+mod options;
+mod swap;
+mod hex;
+mod undofile;
+mod syntax;
+mod tree;
+mod width;
+mod theme;
+mod status;
+mod window;
+mod tab;
+#[cfg(test)]
+mod scratch;
 
 use crossterm::{
     event::{KeyCode, KeyEvent, KeyModifiers}, terminal::size
 };
 use ratatui::DefaultTerminal;
-use std::io;
+use std::{io, rc::Rc, time::{Duration, Instant}};
 
 use command::*;
 use buffer::*;
@@ -27,13 +41,27 @@ struct Editor {
     // buffer stuff
     bufs: Vec<Buffer>,
     active_buf: usize,
+	// windows on screen, active_buf is the one of the active window
+	windows: Vec<window::Window>,
+	layout: window::Layout,
+	active_win: usize,
+	// ctrl+w was pressed, the next key is for windows
+	ctrl_w: bool,
+	// tab pages, the active one is in the fields above
+	tabs: Vec<tab::Tab>,
+	active_tab: usize,
     // misc
     mode: Mode,
     alive: bool,
-	offset: u16,
 	padding: u16,
+	// rows under the buffer taken by the status line
+	status: u16,
     // command stuff
     prompt: Prompt,
+	options: options::Options,
+	theme: theme::Theme,
+	// last time swap files were synced
+	last_sync: Instant,
 	// registers
 	pub reg: ropey::Rope,
 }
@@ -45,11 +73,20 @@ impl Default for Editor {
         Self {
 			bufs: Default::default(), 
             active_buf: Default::default(),
+			windows: vec![window::Window { buf: 0, view: Option::None }],
+			layout: Default::default(),
+			active_win: 0,
+			ctrl_w: false,
+			tabs: vec![Default::default()],
+			active_tab: 0,
             mode: Default::default(), 
             alive: Default::default(), 
-			offset : 5,
 			padding : 1,
+			status : 1,
             prompt,
+			options: Default::default(),
+			theme: Default::default(),
+			last_sync: Instant::now(),
 			reg: Default::default()
         }
     }
@@ -61,14 +98,24 @@ impl Editor {
     fn new_buf(&mut self) {
         let (w, h) = self.get_size();
         self.bufs.push(Buffer::new(w, h));
-        self.active_buf = self.bufs.len() -1;
+        self.show_buf(self.bufs.len() - 1);
     }
 
-    /// gets the editor size in a nice way, 
-    /// used to resize buffers nicely
+    /// gets the editor size in a nice way, the room inside the border.
+    /// buffers take their gutter from it, see fit
     fn get_size(&self) -> (usize, usize){
         let (w, h) = size().unwrap();
-        ((w - self.offset - self.padding * 2 -1) as usize, (h - self.padding * 2) as usize)
+        ((w - self.padding * 2) as usize, (h - self.padding * 2 - self.status) as usize)
+    }
+
+    /// sizes buffer i to a window (w, h inside the border), less its
+    /// gutter and a column for the cursor past the end of a full line
+    fn fit(&mut self, i: usize, (w, h): (usize, usize)) {
+        let buf = &mut self.bufs[i];
+        let w = w.saturating_sub(buf.gutter_width() + 1);
+        if (w, h) != (buf.viewport.width, buf.viewport.height) {
+            buf.resize(w, h);
+        }
     }
 
     fn active_buf(&self) -> &Buffer {
@@ -87,7 +134,19 @@ impl Editor {
         todo!();
     }
     
+    #[allow(clippy::collapsible_match, clippy::single_match)]
     fn handle_insert_mode(&mut self, e : KeyEvent) {
+		if std::mem::take(&mut self.ctrl_w) {
+			if let Err(msg) = self.window_key(e.code) {
+				self.prompt.msg(msg);
+				self.mode = Mode::Command;
+			}
+			return;
+		}
+		if e.modifiers == KeyModifiers::CONTROL && e.code == KeyCode::Char('w') {
+			self.ctrl_w = true;
+			return;
+		}
         
         let buf = self.active_buf_mut();
         match e {
@@ -101,9 +160,25 @@ impl Editor {
 					KeyCode::Right => buf.cursor_mv(Move::Word(1)),
 					KeyCode::Up => buf.viewport_mv(-1),
 					KeyCode::Down => buf.viewport_mv(1),
+					KeyCode::Char('d') => buf.half_page(true),
+					KeyCode::Char('u') => buf.half_page(false),
 					_ => {}
 				}
             }
+			// alt pressed, selects by syntax node and walks the undo history
+			KeyEvent {
+				modifiers: KeyModifiers::ALT,
+				code, ..
+			} => {
+				match code {
+					KeyCode::Up => _ = buf.expand_selection(),
+					KeyCode::Down => _ = buf.shrink_selection(),
+					// g- and g+
+					KeyCode::Char('-') => _ = buf.undo_travel(true, history::Span::Steps(1)),
+					KeyCode::Char('=') => _ = buf.undo_travel(false, history::Span::Steps(1)),
+					_ => {}
+				}
+			}
 			// shift pressed
 			KeyEvent {
 				modifiers: KeyModifiers::SHIFT,
@@ -123,12 +198,15 @@ impl Editor {
                     // key handling
                     KeyCode::Char(c) => buf.insert(c),
                     KeyCode::Enter => buf.insert('\n'),
-					KeyCode::Tab => buf.insert('\t'),
-                    KeyCode::Backspace => buf.delete(1, true),
+					KeyCode::Tab => buf.insert_tab(),
+                    KeyCode::Backspace => buf.backspace(),
                     KeyCode::Delete => buf.delete(1, false),
                     
                     // enter command mode 
-                    KeyCode::Esc => self.mode = Mode::Command,
+                    KeyCode::Esc => {
+						buf.end_insert();
+						self.mode = Mode::Command;
+					},
                     
                     // arrow keys
                     KeyCode::Up => buf.cursor_mv(Move::Exact(Direction::Vert, -1)),
@@ -148,7 +226,26 @@ impl Editor {
         }
     }
     
+    #[allow(clippy::comparison_to_empty)]
     fn handle_command_mode(&mut self, e : KeyCode) {
+		// a question is waiting for an answer, nothing else goes
+		if self.prompt.is_asking() {
+			match e {
+				KeyCode::Char(c) => if let Some(answer) = self.prompt.answer(c) {
+					let res = answer(self);
+					self.command_done(res);
+				},
+				KeyCode::Esc => match self.prompt.dismiss() {
+					Some(answer) => {
+						let res = answer(self);
+						self.command_done(res);
+					},
+					Option::None => self.mode = Mode::Insert,
+				},
+				_ => {}
+			}
+			return;
+		}
         match e {
             KeyCode::Char(c) => self.prompt.insert(c),
             KeyCode::Backspace => {
@@ -159,14 +256,8 @@ impl Editor {
 				let args = self.prompt.parse();
 				if args.is_empty() { self.mode = Mode::Insert; }
 				else if let Some(cmd) = self.prompt.get_command(&args) {
-					match cmd.run(args, self) {
-						Ok(()) => {
-							self.mode = Mode::Insert;
-						},
-						Err(msg) => {
-							self.prompt.msg(msg);
-						}
-					}
+					let res = cmd.run(args, self);
+					self.command_done(res);
 				} else {
 					self.prompt.msg("not a command!".to_owned());
 				}
@@ -182,21 +273,96 @@ impl Editor {
         }
     }
 
+    /// back to insert mode after a command, unless there's something to show
+    fn command_done(&mut self, res: Result<(), String>) {
+		match res {
+			Ok(()) => if !self.prompt.is_asking() && !self.prompt.has_msg() {
+				self.mode = Mode::Insert;
+			},
+			Err(msg) => self.prompt.msg(msg),
+		}
+	}
+
+	/// opens the undo tree popup of the active buffer
+	fn open_undo_tree(&mut self) {
+		let from = self.active_buf().history().seq();
+		self.mode = Mode::UndoTree { from };
+	}
+
+	/// up/down walk the tree (the text follows along), enter keeps the
+	/// state, esc goes back to the one we started from
+	fn handle_undo_tree_mode(&mut self, e: KeyCode, from: usize) {
+		let buf = self.active_buf_mut();
+		let rows = buf.history().layout();
+		let row = rows.iter()
+			.position(|(seq, _)| *seq == buf.history().seq())
+			.unwrap_or(0);
+		match e {
+			KeyCode::Up if row > 0 => buf.undo_goto(rows[row - 1].0),
+			KeyCode::Down => if let Some((seq, _)) = rows.get(row + 1) { buf.undo_goto(*seq) },
+			KeyCode::Enter => self.mode = Mode::Insert,
+			KeyCode::Esc => {
+				buf.undo_goto(from);
+				self.mode = Mode::Insert;
+			},
+			_ => {}
+		}
+	}
+
+	/// checks every buffer against its file on disk.
+	/// unmodified ones are reloaded if autoread is on, for the others
+	/// (one at a time) the user gets asked.
+	fn check_time(&mut self) {
+		for i in 0..self.bufs.len() {
+			let buf = &mut self.bufs[i];
+			if !buf.disk_changed() { continue; }
+
+			let fallback = self.options.fallbackencoding;
+			if !buf.is_modified() && self.options.autoread && buf.reload(fallback).is_ok() {
+				continue;
+			}
+			// dont ask twice about the same version of the file
+			let disk = file::Stamp::read(std::path::Path::new(&buf.filename)).ok();
+			if disk.is_none() || disk == buf.warned { continue; }
+			buf.warned = disk;
+
+			let reload: Answer = Rc::new(move |ed| {
+				let fallback = ed.options.fallbackencoding;
+				ed.bufs[i].reload(fallback).map_err(|e| e.to_string())
+			});
+			let keep: Answer = Rc::new(|_| Ok(()));
+			self.prompt.ask(
+				format!("{} changed on disk: [l]oad it, [k]eep yours", buf.filename),
+				vec![('l', reload), ('k', keep)]
+			);
+			self.mode = Mode::Command;
+			return;
+		}
+	}
+
+	/// housekeeping that runs every now and then, not on keypresses
+	fn tick(&mut self) {
+		if self.last_sync.elapsed() < Duration::from_millis(self.options.updatetime) {
+			return;
+		}
+		for buf in &mut self.bufs {
+			// losing a swap is not worth bothering the user mid typing
+			_ = buf.sync_swap();
+		}
+		self.last_sync = Instant::now();
+	}
+
     fn handle_crossterm_events(&mut self) -> io::Result<()>{
         match crossterm::event::read()? {
             crossterm::event::Event::Key(e) => match self.mode {
                 Mode::Command => self.handle_command_mode(e.code),
                 Mode::Insert => self.handle_insert_mode(e),
+                Mode::UndoTree { from } => self.handle_undo_tree_mode(e.code, from),
                 Mode::Normal => {},
             }
-            crossterm::event::Event::Resize(w, h) => {
-                for buf in &mut self.bufs {
-                    buf.resize(
-						(w - self.offset - self.padding * 2) as usize,
-						(h - self.padding * 2) as usize
-					);
-                }
-            }
+            crossterm::event::Event::FocusGained => self.check_time(),
+            // windows are fit to the layout on every draw
+            crossterm::event::Event::Resize(..) => {},
             _ => {}
         }
         Ok(())
@@ -207,26 +373,48 @@ impl Editor {
     */
     fn run(&mut self, mut terminal: DefaultTerminal) -> io::Result<()> {
         while self.alive {
+			let area = self.windows_area();
+			let windows = self.layout.areas(area);
             terminal.draw(|frame| {
-				let buf = self.active_buf();
-
-				render::render_buffer(frame, buf, &self);
+				for &(w, rect) in &windows {
+					self.in_window(w, |ed, b| {
+						// the gutter grows with the file and comes and goes with `set number`
+						let pad = ed.padding * 2;
+						ed.fit(b, (rect.width.saturating_sub(pad) as usize, rect.height.saturating_sub(pad) as usize));
+						ed.bufs[b].update_syntax();
+						render::render_window(frame, rect, &ed.bufs[b], ed, w == ed.active_win);
+					});
+				}
+				let tabline = ratatui::layout::Rect { y: 0, height: self.tabline(), ..area };
+				render::render_tabline(frame, tabline, self);
+				let status = ratatui::layout::Rect { y: area.bottom(), height: self.status, ..area };
+				render::render_status(frame, status.intersection(frame.area()), self);
 
+				let buf = self.active_buf();
 				match self.mode {
 					Mode::Command => {
-						render::render_command_prompt(frame, &self);
+						render::render_command_prompt(frame, self);
 					},
 					Mode::Insert  => {
 						let (cx, cy) = buf.get_cursor_pos();
+						let rect = windows.iter().find(|(w, _)| *w == self.active_win).map_or(area, |(_, r)| *r);
 						frame.set_cursor_position((
-							cx as u16 + self.padding as u16 + self.offset as u16,
-							cy as u16 + self.padding as u16
+							rect.x + (cx as usize).saturating_sub(buf.viewport.left) as u16 + self.padding + buf.gutter_width() as u16,
+							rect.y + cy as u16 + self.padding
 						));
 					},
+					Mode::UndoTree { .. } => render::render_undo_tree(frame, buf, &self.theme),
 					Mode::Normal => {}
 				}
             })?;
-			self.handle_crossterm_events()?;
+			// wakes up when idle, so swaps get written after typing stops,
+			// or sooner if an undo highlight has to go away
+			let idle = Duration::from_millis(self.options.updatetime);
+			let wait = self.active_buf().flash_left().map_or(idle, |left| left.min(idle));
+			if crossterm::event::poll(wait)? {
+				self.handle_crossterm_events()?;
+			}
+			self.tick();
         }
         Ok(())
     }
@@ -243,7 +431,12 @@ fn main() -> io::Result<()> {
     ed.alive = true;
     
     // run the application
-    ed.run(ratatui::init())
+    let terminal = ratatui::init();
+    // focus events are used to notice files changed while we were away
+    _ = crossterm::execute!(io::stdout(), crossterm::event::EnableFocusChange);
+    let res = ed.run(terminal);
+    _ = crossterm::execute!(io::stdout(), crossterm::event::DisableFocusChange);
+    res
 }
     
 /*
@@ -254,8 +447,11 @@ enum Mode {
     Insert,
     Normal,
     Command,
+	/// picking a state in the undo tree popup, from is where we started
+	UndoTree { from: usize },
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Mode {
     fn default() -> Self {
         Mode::Insert
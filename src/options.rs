@@ -0,0 +1,280 @@
+use crate::file::{self, Format, LineEnding};
+use crate::history::Group;
+use crate::width::ListChars;
+use std::path::PathBuf;
+
+/*
+* options structs - editor and buffer settings, changed at runtime with `:set`
+*
+* each `set` returns None when it doesnt know the option, so `:set` can
+* try the buffer first and the editor after.
+*/
+
+/// editor wide options
+pub struct Options {
+	/// keep the previous version of a file as `file~` when writing
+	pub backup: bool,
+	/// ms of quiet before swap files are written
+	pub updatetime: u64,
+	/// reload unmodified buffers when their file changes on disk
+	pub autoread: bool,
+	/// 8 bit encoding for files that arent utf-8 or utf-16
+	pub fallbackencoding: &'static encoding_rs::Encoding,
+	/// save the undo history of files when writing them
+	pub undofile: bool,
+	/// where undo files go, None if there's no home to put them in
+	pub undodir: Option<PathBuf>,
+	/// format of the status line, see status.rs
+	pub statusline: String,
+	/// put in the gutter of rows that go on from the one above
+	pub showbreak: String,
+	/// glyphs for whitespace with `list`
+	pub listchars: ListChars,
+}
+
+impl Options {
+	pub fn set(&mut self, name: &str, value: Option<&str>) -> Option<Result<(), String>> {
+		match (name, value) {
+			("backup" | "bk", Option::None) => self.backup = true,
+			("nobackup" | "nobk", Option::None) => self.backup = false,
+			("autoread" | "ar", Option::None) => self.autoread = true,
+			("noautoread" | "noar", Option::None) => self.autoread = false,
+			("undofile" | "udf", Option::None) => self.undofile = true,
+			("noundofile" | "noudf", Option::None) => self.undofile = false,
+			("undodir" | "udir", Some(v)) => self.undodir = Some(PathBuf::from(v)),
+			("statusline" | "stl", Some(v)) => self.statusline = v.to_owned(),
+			("showbreak" | "sbr", Some(v)) => self.showbreak = v.to_owned(),
+			("listchars" | "lcs", Some(v)) => match ListChars::parse(v) {
+				Ok(list) => self.listchars = list,
+				Err(e) => return Some(Err(e)),
+			},
+			("updatetime" | "ut", Some(v)) => return Some(parse_num(v).map(|v| self.updatetime = v)),
+			("fallbackencoding" | "fbenc", Some(v)) => match file::encoding_for(v) {
+				Some(e) => self.fallbackencoding = e,
+				Option::None => return Some(Err(format!("unknown encoding: {}", v))),
+			},
+			_ => return Option::None,
+		}
+		Some(Ok(()))
+	}
+}
+
+impl Default for Options {
+	fn default() -> Self {
+		Self {
+			backup: false,
+			updatetime: 4000,
+			autoread: true,
+			fallbackencoding: encoding_rs::WINDOWS_1252,
+			undofile: false,
+			undodir: default_undodir(),
+			statusline: crate::status::DEFAULT.to_owned(),
+			showbreak: "↪".to_owned(),
+			listchars: ListChars::default(),
+		}
+	}
+}
+
+/// `$XDG_STATE_HOME/xi/undo`, or the same under `~/.local/state`
+fn default_undodir() -> Option<PathBuf> {
+	let state = std::env::var_os("XDG_STATE_HOME")
+		.filter(|v| !v.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state")))?;
+	Some(state.join("xi/undo"))
+}
+
+/// options that belong to a single buffer
+pub struct BufOptions {
+	/// refuse to write the buffer
+	pub readonly: bool,
+	/// keep a swap file while the buffer is modified
+	pub swapfile: bool,
+	/// line endings and bom used when writing
+	pub format: Format,
+	/// what counts as one undo step
+	pub undogroup: Group,
+	/// line numbers in the gutter, both on is hybrid:
+	/// the cursor line absolute, the others relative to it
+	pub number: bool,
+	pub relativenumber: bool,
+	/// rows kept on screen above and below the cursor
+	pub scrolloff: usize,
+	/// long lines go on over more rows, or off the right of the screen
+	pub wrap: bool,
+	/// wrapped lines break after a blank, not in the middle of a word
+	pub linebreak: bool,
+	/// show tabs, trailing spaces and line ends, see Options::listchars
+	pub list: bool,
+	/// columns a tab takes on screen
+	pub tabstop: usize,
+	/// tab inserts spaces
+	pub expandtab: bool,
+	/// columns of one indent level, 0 is the same as tabstop
+	pub shiftwidth: usize,
+	/// columns tab and backspace move over in spaces, 0 is off
+	pub softtabstop: usize,
+}
+
+impl BufOptions {
+	pub fn set(&mut self, name: &str, value: Option<&str>) -> Option<Result<(), String>> {
+		match (name, value) {
+			("readonly" | "ro", Option::None) => self.readonly = true,
+			("noreadonly" | "noro", Option::None) => self.readonly = false,
+			("swapfile" | "swf", Option::None) => self.swapfile = true,
+			("noswapfile" | "noswf", Option::None) => self.swapfile = false,
+			("number" | "nu", Option::None) => self.number = true,
+			("nonumber" | "nonu", Option::None) => self.number = false,
+			("relativenumber" | "rnu", Option::None) => self.relativenumber = true,
+			("norelativenumber" | "nornu", Option::None) => self.relativenumber = false,
+			("scrolloff" | "so", Some(v)) => return Some(parse_num(v).map(|v| self.scrolloff = v)),
+			("wrap", Option::None) => self.wrap = true,
+			("nowrap", Option::None) => self.wrap = false,
+			("list", Option::None) => self.list = true,
+			("nolist", Option::None) => self.list = false,
+			("linebreak" | "lbr", Option::None) => self.linebreak = true,
+			("nolinebreak" | "nolbr", Option::None) => self.linebreak = false,
+			("expandtab" | "et", Option::None) => self.expandtab = true,
+			("noexpandtab" | "noet", Option::None) => self.expandtab = false,
+			("tabstop" | "ts", Some(v)) => match parse_num(v) {
+				Ok(0) => return Some(Err("tabstop cant be 0".to_owned())),
+				Ok(v) => self.tabstop = v,
+				Err(e) => return Some(Err(e)),
+			},
+			("shiftwidth" | "sw", Some(v)) => return Some(parse_num(v).map(|v| self.shiftwidth = v)),
+			("softtabstop" | "sts", Some(v)) => return Some(parse_num(v).map(|v| self.softtabstop = v)),
+			("bomb", Option::None) => self.format.bom = true,
+			("nobomb", Option::None) => self.format.bom = false,
+			("fileformat" | "ff", Some(v)) => match LineEnding::from_name(v) {
+				Some(eol) => self.format.eol = eol,
+				Option::None => return Some(Err(format!("invalid fileformat: {}", v))),
+			},
+			("fileencoding" | "fenc", Some(v)) => match file::encoding_for(v) {
+				Some(e) => self.format.encoding = e,
+				Option::None => return Some(Err(format!("unknown encoding: {}", v))),
+			},
+			("undogroup" | "ug", Some(v)) => match Group::from_name(v) {
+				Some(g) => self.undogroup = g,
+				Option::None => return Some(Err(format!("invalid undogroup: {}", v))),
+			},
+			_ => return Option::None,
+		}
+		Some(Ok(()))
+	}
+}
+
+impl Default for BufOptions {
+	fn default() -> Self {
+		Self {
+			readonly: false,
+			swapfile: true,
+			format: Format::default(),
+			undogroup: Group::default(),
+			number: true,
+			relativenumber: false,
+			scrolloff: 0,
+			wrap: true,
+			linebreak: false,
+			list: false,
+			tabstop: 4,
+			expandtab: false,
+			shiftwidth: 0,
+			softtabstop: 0,
+		}
+	}
+}
+
+impl BufOptions {
+	/// columns of one indent level
+	pub fn indent(&self) -> usize {
+		match self.shiftwidth {
+			0 => self.tabstop,
+			sw => sw,
+		}
+	}
+
+	/// columns a tab typed in insert mode moves over
+	pub fn tab_columns(&self) -> usize {
+		match self.softtabstop {
+			0 if self.expandtab => self.indent(),
+			0 => self.tabstop,
+			sts => sts,
+		}
+	}
+}
+
+/// splits a single `:set` argument into name and value.
+/// `name` and `noname` are toggles, `name=value` sets a value.
+pub fn split(arg: &str) -> (&str, Option<&str>) {
+	match arg.split_once('=') {
+		Some((n, v)) => (n, Some(v)),
+		None => (arg, Option::None),
+	}
+}
+
+fn parse_num<T: std::str::FromStr>(v: &str) -> Result<T, String> {
+	v.parse().map_err(|_| format!("not a number: {}", v))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn set(o: &mut Options, arg: &str) -> Option<Result<(), String>> {
+		let (name, value) = split(arg);
+		o.set(name, value)
+	}
+
+	#[test]
+	fn set_toggle_test() {
+		let mut o = Options::default();
+		assert!(!o.backup);
+		assert!(set(&mut o, "backup").unwrap().is_ok());
+		assert!(o.backup);
+		assert!(set(&mut o, "nobk").unwrap().is_ok());
+		assert!(!o.backup);
+		assert!(set(&mut o, "backup=1").is_none());
+		assert!(set(&mut o, "bogus").is_none());
+	}
+
+	#[test]
+	fn set_fileformat_test() {
+		let mut o = BufOptions::default();
+		assert!(o.set("ff", Some("dos")).unwrap().is_ok());
+		assert_eq!(LineEnding::Dos, o.format.eol);
+		assert!(o.set("fileformat", Some("amiga")).unwrap().is_err());
+		assert_eq!(LineEnding::Dos, o.format.eol);
+		assert!(o.set("undogroup", Some("insert")).unwrap().is_ok());
+		assert_eq!(Group::Insert, o.undogroup);
+		assert!(o.set("nonu", Option::None).unwrap().is_ok());
+		assert!(o.set("rnu", Option::None).unwrap().is_ok());
+		assert!(!o.number && o.relativenumber);
+	}
+
+	#[test]
+	fn set_tabs_test() {
+		let mut o = BufOptions::default();
+		assert_eq!((4, 4), (o.indent(), o.tab_columns()));
+		assert!(o.set("ts", Some("8")).unwrap().is_ok());
+		assert!(o.set("sw", Some("2")).unwrap().is_ok());
+		assert!(o.set("et", Option::None).unwrap().is_ok());
+		assert_eq!((8, 2, 2), (o.tabstop, o.indent(), o.tab_columns()));
+		assert!(o.set("sts", Some("3")).unwrap().is_ok());
+		assert_eq!(3, o.tab_columns());
+		assert!(o.set("ts", Some("0")).unwrap().is_err());
+		assert_eq!(8, o.tabstop);
+	}
+
+	#[test]
+	fn set_value_test() {
+		let mut o = Options::default();
+		assert!(set(&mut o, "updatetime=250").unwrap().is_ok());
+		assert_eq!(250, o.updatetime);
+		assert!(set(&mut o, "ut=soon").unwrap().is_err());
+		assert_eq!(250, o.updatetime);
+		assert!(set(&mut o, "lcs=eol:~").unwrap().is_ok());
+		assert_eq!((Some('~'), Option::None), (o.listchars.eol, o.listchars.tab));
+		assert!(set(&mut o, "listchars=eol").unwrap().is_err());
+		assert_eq!(Some('~'), o.listchars.eol);
+	}
+}
@@ -3,83 +3,92 @@ use ratatui::{
 	buffer::Buffer,
 	layout::{Constraint, Layout, Rect},
 	widgets::{Block, Clear, Paragraph, Widget},
-	style::{Style, Color}
+	style::Style
 };
 use crate::buffer::{VisualLine, ViewPort};
 use crate::Editor;
 use crate::selection::Selection;
+use crate::syntax::Highlighter;
+use crate::theme::{Scope, Theme};
+use crate::width::{self, ListChars};
 use std::ops::Range;
 
 pub struct BufferWidget<'a> {
-	line_number_offset: u16,
+	gutter: u16,
+	// gutter shows byte offsets instead of line numbers
+	hex: bool,
+	number: bool,
+	relativenumber: bool,
+	tabstop: usize,
+	// gutter of rows that go on from the one above
+	showbreak: &'a str,
+	// glyphs for whitespace, with `set list`
+	list: Option<&'a ListChars>,
+	// rope line of the cursor, relative numbers count from it
+	cursor_line: usize,
 	rope: &'a ropey::Rope,
 	visual: &'a [VisualLine],
 	viewport: &'a ViewPort,
-	selection: &'a Selection
+	selection: &'a Selection,
+	// text just changed by undo/redo
+	flash: Option<Range<usize>>,
+	// bytes found by the last hex search
+	found: Vec<Range<usize>>,
+	syntax: Option<&'a Highlighter>,
+	theme: &'a Theme,
 }
 
 impl<'a> BufferWidget<'a> {
-	/// using relative cy, unlike Buffer
-	fn visual_to_rope(&self, visual_cx : usize, cy : usize) -> usize {
-		let vl = self.visual[cy + self.viewport.offset];
-		
-		// total offset from the beginning of the rope line
-		// let tot_off = vl.offset + visual_cx;
-		let tab_width = 4;
-		let mut curr_col = 0;
-		let char_cx = self.rope.line(vl.rope)
-			.slice(vl.offset..vl.offset + vl.len)
-			.chars()
-			.take_while(|ch| { 
-				curr_col += if *ch == '\t' {
-					tab_width - (curr_col % tab_width)
-				} else { 1 };
-				curr_col <= visual_cx
-			})
-			.count();
-		let tot_off = vl.offset + char_cx;
+	// this is good, still i might move to do rendering in 2 steps, patching the style.
+	fn divide_and_style(&self, vl: &VisualLine, rope: usize, trail: &Range<usize>) -> Vec<(Range<usize>, Style)> {
+		let default_style = self.theme.style(Scope::Text);
+		let select_style  = default_style.patch(self.theme.style(Scope::Selection));
+		let flash_style   = default_style.patch(self.theme.style(Scope::Flash));
+		let search_style  = default_style.patch(self.theme.style(Scope::Search));
+		let space_style   = default_style.patch(self.theme.style(Scope::Whitespace));
+		let trail_style   = default_style.patch(self.theme.style(Scope::Trailing));
+		let line = rope..(rope + vl.len);
 
-		tot_off + self.rope.line_to_char(vl.rope)
-	}
+		// highlighted ranges, later ones win where they overlap
+		let mut marks = vec![];
+		if let Some(syntax) = self.syntax {
+			let line_start = self.rope.line_to_char(vl.rope);
+			marks.extend(syntax.spans(vl.rope).iter()
+				.map(|s| (
+					line_start + s.range.start..line_start + s.range.end,
+					default_style.patch(self.theme.style(Scope::Syntax(s.kind)))
+				))
+				.filter(|(range, _)| range.start < line.end && range.end > line.start));
+		}
+		// chars shown as list glyphs
+		if self.list.is_some() {
+			marks.extend(self.rope.slice(line.clone()).chars()
+				.enumerate()
+				.filter(|(_, c)| matches!(c, '\t' | '\n' | '\u{a0}' | '\u{202f}') || c.is_control())
+				.map(|(i, _)| (line.start + i..line.start + i + 1, space_style)));
+			marks.push((trail.clone(), space_style));
+		}
+		// not while it's being typed
+		if vl.rope != self.cursor_line { marks.push((trail.clone(), trail_style)); }
+		marks.extend(self.found.iter().map(|range| (range.clone(), search_style)));
+		if self.selection.active { marks.push((self.selection.range_raw(), select_style)); }
+		if let Some(flash) = &self.flash { marks.push((flash.clone(), flash_style)); }
 
-	// this is good, still i might move to do rendering in 2 steps, patching the style.
-	fn divide_and_style(&self, vl: &VisualLine, rope: usize) -> Vec<(Range<usize>, Style)> {
-		let default_style = Style::default();
-		let select_style  = Style::new().bg(Color::White).fg(Color::Black);
-		let select_range = self.selection.range_raw();
-
-		// handles the 3 selection cases, no select, all select, and partial
-		let res = if !self.selection.active 
-			|| select_range.start > rope + vl.len 
-			|| select_range.end < rope {
-			vec![(rope..(rope + vl.len), default_style)]
-		} else if select_range.start < rope && select_range.end > rope + vl.len {
-			vec![(rope..(rope + vl.len), select_style)]
-		} else {
-			vec![
-				(rope..select_range.start, default_style),
-				(select_range.start.max(rope)..select_range.end.min(rope + vl.len), select_style),
-				(select_range.end..(rope + vl.len), default_style)
-			]
-		};
+		// cut the line where any highlight starts or ends, and where trailing spaces start
+		let mut cuts = vec![line.start, line.end, trail.start.clamp(line.start, line.end)];
+		for (range, _) in &marks {
+			cuts.extend([range.start, range.end].into_iter().filter(|c| line.contains(c)));
+		}
+		cuts.sort_unstable();
+		cuts.dedup();
 
 		// indexing into the rope with invalid ranges makes it exolode
-		res.into_iter().filter(|(range, _)| !range.is_empty()).collect()
-	}
-
-	/// crappy tab expansion for the rendering..
-	fn expand_tabs(&self, slice: ropey::RopeSlice<'_>, mut vis_col: usize) -> String { 
-		let tab_size = 4;
-		slice.chars()
-			.flat_map(|ch| {
-				if ch == '\t' {
-					let spaces = tab_size - (vis_col % tab_size);
-					vis_col += spaces;
-					std::iter::repeat(' ').take(spaces).collect::<Vec<_>>()
-				} else {
-					vis_col += 1;
-					vec![ch]
-				}
+		cuts.windows(2)
+			.map(|w| {
+				let style = marks.iter().rev()
+					.find(|(range, _)| range.contains(&w[0]))
+					.map_or(default_style, |(_, style)| *style);
+				(w[0]..w[1], style)
 			})
 			.collect()
 	}
@@ -91,7 +100,7 @@ impl<'a> Widget for BufferWidget<'a> {
 		let layout = Layout::default()
 			.direction(ratatui::layout::Direction::Horizontal)
 			.constraints([
-				Constraint::Length(self.line_number_offset),
+				Constraint::Length(self.gutter),
 				Constraint::Min(5)
 			])
 			.split(area);
@@ -104,58 +113,137 @@ impl<'a> Widget for BufferWidget<'a> {
 		// let mut ln_buf = String::new();
 		for (i, vl) in vls.iter().enumerate() {
 
-			// print line numbers
-			if i == 0 || vls[i -1].rope != vl.rope {
-				// writing a char is faster than allocating a string
-				// ln_buf.clear();
-				// write!(&mut ln_buf, "{}", vl.rope).unwrap();
+			// print line numbers, wrapped lines only get one
+			if vl.offset == 0 && self.gutter > 0 {
+				let width = self.gutter as usize - 1;
+				let distance = vl.rope.abs_diff(self.cursor_line);
+				let label = match (self.hex, self.number, self.relativenumber) {
+					(true, _, _) => format!("{:08x}", vl.rope * crate::hex::ROW),
+					// hybrid, the cursor line is absolute and sticks out to the left
+					(_, true, true) if distance == 0 => format!("{:<width$}", vl.rope + 1),
+					(_, _, true) => format!("{:>width$}", distance),
+					_ => format!("{:>width$}", vl.rope + 1),
+				};
 				buf.set_stringn(
 					layout[0].x,
 					layout[0].y + i as u16,
-					vl.rope.to_string(),
-					self.line_number_offset as usize,
-					Style::default(),
+					label,
+					width,
+					self.theme.style(Scope::LineNumber),
+				);
+			} else if self.gutter > 0 && !self.hex {
+				let width = self.gutter as usize - 1;
+				buf.set_stringn(
+					layout[0].x,
+					layout[0].y + i as u16,
+					format!("{:>width$}", self.showbreak),
+					width,
+					self.theme.style(Scope::LineNumber),
 				);
 			}
-			
+
 			// divide shit into styled chunks
-			let start = self.visual_to_rope(0, i);
-			let chunks = self.divide_and_style(&vl, start);
-			let mut x = layout[1].x;
+			let start = self.rope.line_to_char(vl.rope) + vl.offset;
+			let trail = trailing(self.rope, vl.rope);
+			let chunks = self.divide_and_style(vl, start, &trail);
+			// columns of the row, only the ones from viewport.left on are shown
+			let (left, right) = (self.viewport.left, self.viewport.left + layout[1].width as usize);
+			let mut col = 0;
 			let y = layout[1].y + i as u16;
 			for (range, style) in chunks {
-				// printing the text
-				let text = self.rope.slice(range);
-				let text = self.expand_tabs(text, (x - layout[1].x) as usize);
-				let text_width = text.len();
-				buf.set_string(x, y, text, style);
-				x += text_width as u16;
+				let slice = self.rope.slice(range.clone());
+				let end = col + width::str_width(slice, col, self.tabstop);
+				if end > left && col < right {
+					// printing the text
+					let mut text = width::expand_tabs(slice, col, self.tabstop, self.list, range.start >= trail.start);
+					if col < left { text = width::skip_cols(&text, left - col); }
+					let x = layout[1].x + (col.max(left) - left) as u16;
+					// ratatui knows how wide graphemes are, same as width.rs
+					buf.set_stringn(x, y, text, right - col.max(left), style);
+				}
+				col = end;
 			}
 		}
 	}
 }
 
-pub fn render_buffer(frame: &mut Frame, buf: &crate::buffer::Buffer, ed: &Editor) {
-	let outline = Block::bordered().title(
-			"<".to_owned() + &ed.active_buf.to_string() + ": " + &buf.filename
-			+ match buf.is_modified() { true => "*", false => "" }
-			+ ">"
-		)
-		.title_alignment(ratatui::layout::Alignment::Right);
-	let outline_area = outline.inner(frame.area());
-	frame.render_widget(outline, frame.area());
+/// spaces and tabs at the end of rope line, before the line break
+fn trailing(rope: &ropey::Rope, line: usize) -> Range<usize> {
+	let start = rope.line_to_char(line);
+	let text = rope.line(line);
+	let mut end = text.len_chars();
+	while end > 0 && matches!(text.char(end - 1), '\n' | '\r') { end -= 1; }
+	let mut trail = end;
+	while trail > 0 && matches!(text.char(trail - 1), ' ' | '\t') { trail -= 1; }
+	start + trail..start + end
+}
+
+/// a window showing buf in area, only the active one has its title stand out
+pub fn render_window(frame: &mut Frame, area: Rect, buf: &crate::buffer::Buffer, ed: &Editor, active: bool) {
+	// the rest of what used to be in the title is on the status line
+	let title_style = match active {
+		true => ed.theme.style(Scope::Title),
+		false => ed.theme.style(Scope::Border),
+	};
+	let outline = Block::bordered().title(buf.filename.as_str())
+		.title_alignment(ratatui::layout::Alignment::Right)
+		.style(ed.theme.style(Scope::Text))
+		.border_style(ed.theme.style(Scope::Border))
+		.title_style(title_style);
+	let outline_area = outline.inner(area);
+	frame.render_widget(outline, area);
 	frame.render_widget(
 		BufferWidget {
-			line_number_offset: ed.offset,
+			gutter: buf.gutter_width() as u16,
+			hex: buf.hex,
+			number: buf.options.number,
+			relativenumber: buf.options.relativenumber,
+			tabstop: buf.options.tabstop,
+			showbreak: &ed.options.showbreak,
+			list: buf.options.list.then_some(&ed.options.listchars),
+			cursor_line: buf.lines.char_to_line(buf.cs()),
 			rope: &buf.lines,
 			visual: &buf.visual,
 			viewport: &buf.viewport,
-			selection: &buf.selection
+			selection: &buf.selection,
+			flash: buf.flash(),
+			found: buf.found(),
+			syntax: buf.syntax.as_ref(),
+			theme: &ed.theme,
 		},
 		outline_area
 	);
 }
 
+/// the tab bar, the active tab stands out
+pub fn render_tabline(frame: &mut Frame, area: Rect, ed: &Editor) {
+	if area.height == 0 { return; }
+	let buf = frame.buffer_mut();
+	let style = ed.theme.style(Scope::TabLine);
+	buf.set_stringn(area.x, area.y, " ".repeat(area.width as usize), area.width as usize, style);
+	let mut x = area.x;
+	for (t, label) in ed.tab_labels().into_iter().enumerate() {
+		let style = match t == ed.active_tab {
+			true => style.patch(ed.theme.style(Scope::TabSel)),
+			false => style,
+		};
+		let room = area.right().saturating_sub(x) as usize;
+		(x, _) = buf.set_stringn(x, area.y, format!(" {} ", label), room, style);
+	}
+}
+
+/// the status line, the part after `%=` goes to the right
+pub fn render_status(frame: &mut Frame, area: Rect, ed: &Editor) {
+	if area.height == 0 { return; }
+	let (left, right) = crate::status::format(&ed.options.statusline, ed);
+	let style = ed.theme.style(Scope::Status);
+	let width = area.width as usize;
+	let right_width = right.chars().count().min(width);
+	let gap = width.saturating_sub(left.chars().count() + right_width + 2);
+	let line = format!(" {}{}{} ", left, " ".repeat(gap), right);
+	frame.buffer_mut().set_stringn(area.x, area.y, line, width, style);
+}
+
 pub fn render_command_prompt(frame: &mut Frame, ed: &Editor) {
 	let prompt_area = Rect {
 		x: frame.area().x,
@@ -163,9 +251,12 @@ pub fn render_command_prompt(frame: &mut Frame, ed: &Editor) {
 		width: frame.area().width,
 		height: ed.padding * 2 + 1
 	};
-	let prompt_outline = Block::bordered().title(":");
+	let prompt_outline = Block::bordered().title(":")
+		.border_style(ed.theme.style(Scope::Border))
+		.title_style(ed.theme.style(Scope::Title));
 	let disp = ed.prompt.display();
 	let prompt = Paragraph::new(disp.0)
+		.style(ed.theme.style(Scope::Prompt))
 		.block(prompt_outline);
 	frame.render_widget(Clear, prompt_area);
 	frame.render_widget(prompt, prompt_area);
@@ -176,3 +267,90 @@ pub fn render_command_prompt(frame: &mut Frame, ed: &Editor) {
 	));
 }
 
+
+/// the undo tree of buf in a popup on the right, the current state is
+/// highlighted and `w` marks the last written one
+pub fn render_undo_tree(frame: &mut Frame, buf: &crate::buffer::Buffer, theme: &Theme) {
+	let history = buf.history();
+	let area = frame.area();
+	let width = 32.min(area.width);
+	let popup = Rect { x: area.width - width, y: area.y, width, height: area.height };
+	let outline = Block::bordered().title("undo tree")
+		.style(theme.style(Scope::Text))
+		.border_style(theme.style(Scope::Border))
+		.title_style(theme.style(Scope::Title));
+	let inner = outline.inner(popup);
+
+	let rows = history.layout();
+	let curr = rows.iter().position(|(seq, _)| *seq == history.seq()).unwrap_or(0);
+	// keep the current row in view
+	let skip = (curr + 1).saturating_sub(inner.height as usize);
+
+	frame.render_widget(Clear, popup);
+	frame.render_widget(outline, popup);
+	for (i, (seq, depth)) in rows.iter().enumerate().skip(skip).take(inner.height as usize) {
+		let state = history.state(*seq);
+		let label = format!(
+			"{}{} {}{}",
+			"| ".repeat(*depth),
+			seq,
+			match seq { 0 => "original".to_owned(), _ => crate::history::ago(state.time) },
+			if *seq == history.saved() { " w" } else { "" }
+		);
+		let style = match i == curr {
+			true => theme.style(Scope::Text).patch(theme.style(Scope::Selection)),
+			false => theme.style(Scope::Text),
+		};
+		frame.buffer_mut().set_stringn(
+			inner.x, inner.y + (i - skip) as u16, label, inner.width as usize, style
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// the text area of buf drawn with list or without
+	fn draw(buf: &crate::buffer::Buffer, list: Option<&ListChars>, theme: &Theme) -> Buffer {
+		let area = Rect::new(0, 0, 12, 2);
+		let mut out = Buffer::empty(area);
+		BufferWidget {
+			gutter: 0,
+			hex: false,
+			number: false,
+			relativenumber: false,
+			tabstop: 4,
+			showbreak: "",
+			list,
+			cursor_line: 1,
+			rope: &buf.lines,
+			visual: &buf.visual,
+			viewport: &buf.viewport,
+			selection: &buf.selection,
+			flash: None,
+			found: vec![],
+			syntax: None,
+			theme,
+		}.render(area, &mut out);
+		out
+	}
+
+	#[test]
+	fn list_test() {
+		let buf = crate::buffer::Buffer::open("a".to_owned(), ropey::Rope::from("\tab  \nc "), 11, 2);
+		let theme = Theme::default();
+		let out = draw(&buf, Some(&ListChars::default()), &theme);
+		let row = |out: &Buffer, y| (0..12).map(|x| out[(x, y)].symbol()).collect::<String>();
+		assert_eq!(">   ab--$   ", row(&out, 0));
+		assert_eq!("c-          ", row(&out, 1));
+		assert_eq!(theme.style(Scope::Whitespace).fg, out[(0, 0)].style().fg);
+
+		let out = draw(&buf, None, &theme);
+		let trail = theme.style(Scope::Trailing).bg;
+		assert_eq!(trail, out[(6, 0)].style().bg, "trailing spaces");
+		assert_ne!(trail, out[(5, 0)].style().bg);
+		assert_ne!(trail, out[(1, 1)].style().bg, "not on the cursor line");
+		assert_eq!("c", out[(0, 1)].symbol());
+	}
+}
@@ -0,0 +1,37 @@
+/*
+* scratch dirs - somewhere for tests to write files, gone again once the
+* test is done (or panics)
+*/
+use std::{fs, ops::Deref, path::{Path, PathBuf}};
+
+pub struct Scratch(PathBuf);
+
+impl Scratch {
+	/// a fresh empty dir, name has to be unique among the tests
+	pub fn new(name: &str) -> Scratch {
+		let dir = std::env::temp_dir().join(format!("xi-{}-{}", name, std::process::id()));
+		_ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		Scratch(dir)
+	}
+}
+
+impl Deref for Scratch {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for Scratch {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for Scratch {
+	fn drop(&mut self) {
+		_ = fs::remove_dir_all(&self.0);
+	}
+}
@@ -1,13 +1,15 @@
 use std::ops::Range;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 #[allow(unused)]
 pub struct Selection {
 	pub active: bool,
 	// both are indexes into the rope
 	pub anchor: usize,
 	pub end: usize,
-	pub mode: SelectionMode
+	pub mode: SelectionMode,
+	// what expand_selection grew the selection from, last one on top
+	pub expanded: Vec<Range<usize>>,
 }
 
 #[allow(unused)]
@@ -43,13 +45,14 @@ impl Selection {
 	}
 }
 
+#[derive(Clone)]
 #[allow(unused)]
 pub enum SelectionMode {
 	Line,
 	Char
 }
 
-#[allow(unused)]
+#[allow(unused, clippy::derivable_impls)]
 impl Default for SelectionMode {
 	fn default() -> Self {
 		SelectionMode::Char
@@ -0,0 +1,130 @@
+/*
+* status line - one row under the buffer, built from a format string
+* (`:set statusline=...`). items start with `%`, everything else is copied:
+*
+*   %m mode          %n buffer number  %f file name   %M `*` if modified
+*   %r `[ro]`        %y file type      %e encoding    %o line endings
+*   %l line          %L line count     %c column      %v screen column
+*   %V `-` and the screen column, if it isnt the column
+*   %p how far through the file, in percent
+*   %s size of the selection          %k the command being typed
+*   %= the rest goes to the right     %% a `%`
+*/
+use crate::{Editor, Mode};
+
+pub const DEFAULT: &str = "%m  %n: %f%M%r%=%k  %s  %y  %e %o  %l:%c%V  %p";
+
+/// the left and right part of the status line
+pub fn format(fmt: &str, ed: &Editor) -> (String, String) {
+	let buf = ed.active_buf();
+	let lines = &buf.lines;
+	let cs = buf.cs();
+	let line = lines.char_to_line(cs);
+	let col = cs - lines.line_to_char(line) + 1;
+	let screen_col = buf.get_cursor_pos().0 as usize + 1;
+
+	let (mut left, mut right) = (String::new(), String::new());
+	let mut out = &mut left;
+	let mut chars = fmt.chars();
+	while let Some(c) = chars.next() {
+		if c != '%' {
+			out.push(c);
+			continue;
+		}
+		let item = match chars.next() {
+			Some('m') => mode(&ed.mode).to_owned(),
+			Some('n') => ed.active_buf.to_string(),
+			Some('f') => buf.filename.clone(),
+			Some('M') => if buf.is_modified() { "*".to_owned() } else { String::new() },
+			Some('r') => if buf.options.readonly { " [ro]".to_owned() } else { String::new() },
+			Some('y') => match (&buf.syntax, buf.hex) {
+				(_, true) => "hex".to_owned(),
+				(Some(syntax), _) => syntax.lang().name().to_owned(),
+				(Option::None, _) => "text".to_owned(),
+			},
+			Some('e') => {
+				let format = &buf.options.format;
+				let bom = if format.bom { "+bom" } else { "" };
+				format.encoding.name().to_lowercase() + bom
+			},
+			Some('o') => buf.options.format.eol.name().to_owned(),
+			Some('l') => (line + 1).to_string(),
+			Some('L') => lines.len_lines().to_string(),
+			Some('c') => col.to_string(),
+			Some('v') => screen_col.to_string(),
+			Some('V') if screen_col != col => format!("-{}", screen_col),
+			Some('V') => String::new(),
+			Some('p') => format!("{}%", (line + 1) * 100 / lines.len_lines()),
+			Some('s') => selection(ed),
+			Some('k') => match ed.mode {
+				Mode::Command => ed.prompt.typed().to_owned(),
+				_ => String::new(),
+			},
+			Some('=') => {
+				out = &mut right;
+				continue;
+			},
+			Some(c) => c.to_string(),
+			Option::None => break,
+		};
+		out.push_str(&item);
+	}
+	(left, right)
+}
+
+fn mode(mode: &Mode) -> &'static str {
+	match mode {
+		Mode::Insert => "INSERT",
+		Mode::Normal => "NORMAL",
+		Mode::Command => "COMMAND",
+		Mode::UndoTree { .. } => "UNDO TREE",
+	}
+}
+
+/// `12 chars`, or `3 lines, 40 chars` across lines
+fn selection(ed: &Editor) -> String {
+	let buf = ed.active_buf();
+	if !buf.selection.active { return String::new(); }
+	let text = buf.selection.ctx(&buf.lines);
+	match text.len_lines() {
+		1 => format!("{} chars", text.len_chars()),
+		n => format!("{} lines, {} chars", n, text.len_chars()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::buffer::{Buffer, Direction, Move};
+
+	fn editor(text: &str) -> Editor {
+		let mut ed = Editor::default();
+		ed.bufs.push(Buffer::open("a.rs".to_owned(), ropey::Rope::from(text), 40, 10));
+		ed
+	}
+
+	#[test]
+	fn format_test() {
+		let mut ed = editor("fn a() {}\n\tb\nc\nd");
+		assert_eq!(("INSERT 0: a.rs rust".to_owned(), "1:1 25%".to_owned()), format("%m %n: %f%M %y%=%l:%c %p", &ed));
+		ed.active_buf_mut().insert('x');
+		ed.active_buf_mut().options.readonly = true;
+		assert_eq!("a.rs* [ro]", format("%f%M%r", &ed).0);
+		assert_eq!("utf-8 unix 100%", format("%e %o 100%%", &ed).0);
+
+		ed.bufs[0].cursor_mv(Move::Exact(Direction::Vert, 1));
+		ed.bufs[0].cursor_end();
+		assert_eq!("2:3-6 of 4", format("%l:%c%V of %L", &ed).0);
+		assert_eq!("", format("%s", &ed).0);
+	}
+
+	#[test]
+	fn selection_test() {
+		let mut ed = editor("abc\ndef");
+		ed.active_buf_mut().selection_begin();
+		ed.active_buf_mut().cursor_mv(Move::Exact(Direction::Horiz, 2));
+		assert_eq!("2 chars", format("%s", &ed).0);
+		ed.active_buf_mut().cursor_mv(Move::Exact(Direction::Vert, 1));
+		assert_eq!("2 lines, 6 chars", format("%s", &ed).0);
+	}
+}
@@ -0,0 +1,120 @@
+/*
+* swap files - a copy of every modified buffer is kept next to its file,
+* so a dead terminal doesnt take unsaved work with it
+*/
+use crate::file;
+use std::{fs, io::{self, BufRead, Read}, path::{Path, PathBuf}};
+
+const MAGIC: &str = "xi swap 1";
+
+/// the content of a swap file
+pub struct Swap {
+	/// process that wrote the swap
+	pub pid: u32,
+	pub filename: String,
+	pub cs: usize,
+	pub text: ropey::Rope,
+}
+
+impl Swap {
+	/// true if the editor that wrote this swap is gone
+	pub fn is_stale(&self) -> bool {
+		if self.pid == std::process::id() { return false; }
+		!process_alive(self.pid)
+	}
+}
+
+/// swap file used for filename, `dir/.name.swp`
+pub fn swap_path(filename: &str) -> PathBuf {
+	let path = Path::new(filename);
+	let name = path.file_name()
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	path.with_file_name(format!(".{}.swp", name))
+}
+
+/// writes (or replaces) the swap file for filename
+pub fn write(filename: &str, cs: usize, text: &ropey::Rope) -> io::Result<()> {
+	file::write_atomic(&swap_path(filename), false, |wr| {
+		write!(wr, "{}\npid {}\ncs {}\nfile {}\n\n", MAGIC, std::process::id(), cs, filename)?;
+		text.write_to(wr)
+	})
+}
+
+/// reads the swap file for filename, if there is one
+pub fn read(filename: &str) -> io::Result<Swap> {
+	let mut rd = io::BufReader::new(fs::File::open(swap_path(filename))?);
+	let bad = || io::Error::new(io::ErrorKind::InvalidData, "not a swap file");
+
+	let mut line = String::new();
+	rd.read_line(&mut line)?;
+	if line.trim_end() != MAGIC { return Err(bad()); }
+
+	let mut swap = Swap { pid: 0, filename: String::new(), cs: 0, text: ropey::Rope::new() };
+	loop {
+		line.clear();
+		rd.read_line(&mut line)?;
+		let header = line.trim_end_matches('\n');
+		if header.is_empty() { break; }
+		match header.split_once(' ') {
+			Some(("pid", v)) => swap.pid = v.parse().map_err(|_| bad())?,
+			Some(("cs", v)) => swap.cs = v.parse().map_err(|_| bad())?,
+			Some(("file", v)) => swap.filename = v.to_owned(),
+			_ => return Err(bad()),
+		}
+	}
+	let mut text = String::new();
+	rd.read_to_string(&mut text)?;
+	swap.text = ropey::Rope::from(text);
+	swap.cs = swap.cs.min(swap.text.len_chars());
+	Ok(swap)
+}
+
+/// removes the swap file for filename, a missing swap is not an error
+pub fn remove(filename: &str) -> io::Result<()> {
+	match fs::remove_file(swap_path(filename)) {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		res => res,
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+	Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/// no cheap way to ask elsewhere, better to offer a recovery too many
+#[cfg(not(target_os = "linux"))]
+fn process_alive(_pid: u32) -> bool {
+	false
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::scratch::Scratch;
+
+	#[test]
+	fn swap_path_test() {
+		assert_eq!(PathBuf::from("dir/.a.txt.swp"), swap_path("dir/a.txt"));
+		assert_eq!(PathBuf::from(".a.txt.swp"), swap_path("a.txt"));
+	}
+
+	#[test]
+	fn round_trip_test() {
+		let dir = Scratch::new("swap");
+		let filename = dir.join("f.txt").to_string_lossy().into_owned();
+		let text = ropey::Rope::from("line 1\n\nline 3\n");
+
+		write(&filename, 9, &text).unwrap();
+		let swap = read(&filename).unwrap();
+		assert_eq!(text, swap.text);
+		assert_eq!(9, swap.cs);
+		assert_eq!(filename, swap.filename);
+		assert!(!swap.is_stale(), "we are still alive");
+
+		remove(&filename).unwrap();
+		assert!(read(&filename).is_err());
+		assert!(remove(&filename).is_ok());
+	}
+}
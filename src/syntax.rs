@@ -0,0 +1,785 @@
+/*
+* syntax highlighting - every language is a small hand written tokenizer that
+* works a line at a time. whatever a line leaves open (a block comment, a
+* string, a code fence) is passed on to the next one as a State.
+*
+* a Highlighter caches the spans and the starting state of every line it has
+* seen, edits throw away the cache from the edited line down and only what
+* ends up on screen gets tokenized again.
+*
+* languages with a tree-sitter grammar (see tree.rs) are highlighted from the
+* syntax tree instead, the tokenizers are left for when there is none.
+*/
+use crate::tree::{self, SyntaxTree};
+use std::ops::Range;
+
+/// what a piece of text is, the theme picks the colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+	Keyword,
+	Type,
+	Function,
+	String,
+	Number,
+	Comment,
+	Constant,
+	Macro,
+	Attribute,
+	/// keys of toml and json
+	Key,
+	/// shell variables
+	Variable,
+	Heading,
+	Emphasis,
+	/// inline code and code blocks in markdown
+	Code,
+	Link,
+}
+
+/// a highlighted piece of a line, in chars from the start of the line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+	pub range: Range<usize>,
+	pub kind: Kind,
+}
+
+/// what a line leaves open for the next one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum State {
+	#[default]
+	Normal,
+	/// inside a block comment, nested this deep
+	Comment(u8),
+	/// inside a string closed by this quote
+	Str(char),
+	/// inside a toml `"""` or `'''` string
+	LongStr(char),
+	/// inside a markdown code fence
+	Fence,
+}
+
+pub trait Language: Sync {
+	fn name(&self) -> &'static str;
+	/// file extensions, or whole file names, the language is used for
+	fn extensions(&self) -> &'static [&'static str];
+	/// tokenizes one line (without its `\n`) that starts in state.
+	/// returns the spans and the state the next line starts in.
+	fn line(&self, line: &str, state: State) -> (Vec<Span>, State);
+}
+
+static LANGUAGES: &[&dyn Language] = &[&Rust, &Markdown, &Toml, &Json, &Shell];
+
+/// picks a language by the extension (or name) of a file
+pub fn for_file(filename: &str) -> Option<&'static dyn Language> {
+	let path = std::path::Path::new(filename);
+	let name = path.file_name()?.to_str()?;
+	let ext = path.extension().and_then(|e| e.to_str());
+	LANGUAGES.iter()
+		.find(|l| l.extensions().iter().any(|e| Some(*e) == ext || *e == name))
+		.copied()
+}
+
+/// picks a language by its name
+pub fn by_name(name: &str) -> Option<&'static dyn Language> {
+	LANGUAGES.iter().find(|l| l.name() == name).copied()
+}
+
+/// the highlighting of a buffer
+pub struct Highlighter {
+	lang: &'static dyn Language,
+	/// for languages with a grammar
+	tree: Option<SyntaxTree>,
+	/// state at the start of each line, one more than spans
+	states: Vec<State>,
+	/// spans of the lines tokenized so far
+	spans: Vec<Vec<Span>>,
+}
+
+impl Highlighter {
+	pub fn new(lang: &'static dyn Language) -> Self {
+		let tree = tree::grammar(lang.name()).and_then(SyntaxTree::new);
+		Highlighter { lang, tree, states: vec![State::Normal], spans: vec![] }
+	}
+
+	pub fn lang(&self) -> &'static dyn Language {
+		self.lang
+	}
+
+	/// line was edited, it and everything after it has to be tokenized again.
+	/// the state line starts in is still good.
+	pub fn invalidate(&mut self, line: usize) {
+		self.spans.truncate(line);
+		self.states.truncate(line + 1);
+	}
+
+	/// the text is about to change at char at: removed chars go, inserted comes in
+	pub fn edit(&mut self, rope: &ropey::Rope, at: usize, removed: usize, inserted: &str) {
+		self.invalidate(rope.char_to_line(at));
+		if let Some(tree) = &mut self.tree {
+			tree.edit(rope, at, removed, inserted);
+		}
+	}
+
+	pub fn has_tree(&self) -> bool {
+		self.tree.is_some()
+	}
+
+	/// the syntax tree, parsed up to date with rope
+	pub fn tree(&mut self, rope: &ropey::Rope) -> Option<&SyntaxTree> {
+		let first = self.tree.as_mut()?.parse(rope);
+		if let Some(line) = first { self.invalidate(line); }
+		self.tree.as_ref()
+	}
+
+	/// tokenizes whatever isnt cached up to line last
+	pub fn update(&mut self, rope: &ropey::Rope, last: usize) {
+		let last = last.min(rope.len_lines() - 1);
+		if let Some(tree) = &mut self.tree {
+			// tree only borrows the field, invalidate would borrow all of self
+			if let Some(line) = tree.parse(rope) { self.spans.truncate(line); }
+			let from = self.spans.len();
+			if from <= last { self.spans.extend(tree.highlight(rope, from..last + 1)); }
+			return;
+		}
+		while self.spans.len() <= last {
+			let n = self.spans.len();
+			let mut text = rope.line(n).to_string();
+			if text.ends_with('\n') { text.pop(); }
+			let (spans, next) = self.lang.line(&text, self.states[n]);
+			self.spans.push(spans);
+			self.states.push(next);
+		}
+	}
+
+	/// spans of a line, empty if it wasnt tokenized
+	pub fn spans(&self, line: usize) -> &[Span] {
+		self.spans.get(line).map_or(&[], |s| s.as_slice())
+	}
+}
+
+/*
+* scanner - the bits every tokenizer needs
+*/
+struct Scanner {
+	chars: Vec<char>,
+	pos: usize,
+	spans: Vec<Span>,
+}
+
+impl Scanner {
+	fn new(line: &str) -> Self {
+		Scanner { chars: line.chars().collect(), pos: 0, spans: vec![] }
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.pos).copied()
+	}
+
+	fn peek_at(&self, n: usize) -> Option<char> {
+		self.chars.get(self.pos + n).copied()
+	}
+
+	fn starts_with(&self, s: &str) -> bool {
+		s.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+	}
+
+	fn eat_while(&mut self, f: impl Fn(char) -> bool) {
+		while self.peek().is_some_and(&f) { self.pos += 1; }
+	}
+
+	fn skip_rest(&mut self) {
+		self.pos = self.chars.len();
+	}
+
+	/// highlights from start to where the scanner is
+	fn mark(&mut self, start: usize, kind: Kind) {
+		if self.pos > start {
+			self.spans.push(Span { range: start..self.pos, kind });
+		}
+	}
+
+	/// eats an identifier and returns it
+	fn word(&mut self) -> String {
+		let start = self.pos;
+		self.eat_while(|c| c.is_alphanumeric() || c == '_');
+		self.chars[start..self.pos].iter().collect()
+	}
+
+	/// eats the rest of a string closed by quote, true if it got closed
+	fn string(&mut self, quote: char, escapes: bool) -> bool {
+		while let Some(c) = self.peek() {
+			self.pos += 1;
+			if c == '\\' && escapes {
+				self.pos += 1;
+			} else if c == quote {
+				return true;
+			}
+		}
+		self.pos = self.pos.min(self.chars.len());
+		false
+	}
+
+	/// eats numbers in most of their forms: 0x1f, 1_000, 2.5e3, 10u8
+	fn number(&mut self) {
+		self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_');
+		if self.peek() == Some('.') && self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) {
+			self.pos += 1;
+			self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_');
+		}
+	}
+
+	/// eats the rest of a block comment, returns how deep it still is
+	fn block_comment(&mut self, mut depth: u8, open: &str, close: &str) -> u8 {
+		while self.peek().is_some() {
+			if self.starts_with(close) {
+				self.pos += close.len();
+				depth -= 1;
+				if depth == 0 { break; }
+			} else if self.starts_with(open) {
+				self.pos += open.len();
+				depth = depth.saturating_add(1);
+			} else {
+				self.pos += 1;
+			}
+		}
+		depth
+	}
+
+	fn done(self, state: State) -> (Vec<Span>, State) {
+		(self.spans, state)
+	}
+}
+
+fn is_ident_start(c: char) -> bool {
+	c.is_alphabetic() || c == '_'
+}
+
+/*
+* rust
+*/
+struct Rust;
+
+const RUST_KEYWORDS: &[&str] = &[
+	"as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+	"extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+	"pub", "ref", "return", "self", "static", "struct", "super", "trait", "type", "unsafe",
+	"use", "where", "while", "yield",
+];
+
+const RUST_TYPES: &[&str] = &[
+	"bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize",
+	"i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64", "Self",
+];
+
+impl Language for Rust {
+	fn name(&self) -> &'static str { "rust" }
+	fn extensions(&self) -> &'static [&'static str] { &["rs"] }
+
+	fn line(&self, line: &str, state: State) -> (Vec<Span>, State) {
+		let mut sc = Scanner::new(line);
+		match state {
+			State::Comment(depth) => {
+				let depth = sc.block_comment(depth, "/*", "*/");
+				sc.mark(0, Kind::Comment);
+				if depth > 0 { return sc.done(State::Comment(depth)); }
+			},
+			State::Str(q) => {
+				let closed = sc.string(q, true);
+				sc.mark(0, Kind::String);
+				if !closed { return sc.done(state); }
+			},
+			_ => {},
+		}
+
+		while let Some(c) = sc.peek() {
+			let start = sc.pos;
+			match c {
+				'/' if sc.starts_with("//") => {
+					sc.skip_rest();
+					sc.mark(start, Kind::Comment);
+				},
+				'/' if sc.starts_with("/*") => {
+					sc.pos += 2;
+					let depth = sc.block_comment(1, "/*", "*/");
+					sc.mark(start, Kind::Comment);
+					if depth > 0 { return sc.done(State::Comment(depth)); }
+				},
+				'"' => {
+					sc.pos += 1;
+					let closed = sc.string('"', true);
+					sc.mark(start, Kind::String);
+					if !closed { return sc.done(State::Str('"')); }
+				},
+				// a char, or a lifetime
+				'\'' if sc.peek_at(1) == Some('\\') || sc.peek_at(2) == Some('\'') => {
+					sc.pos += 1;
+					sc.string('\'', true);
+					sc.mark(start, Kind::String);
+				},
+				'\'' => {
+					sc.pos += 1;
+					sc.word();
+					sc.mark(start, Kind::Type);
+				},
+				'#' if sc.starts_with("#[") || sc.starts_with("#![") => {
+					let mut depth = 0;
+					while let Some(c) = sc.peek() {
+						sc.pos += 1;
+						match c {
+							'[' => depth += 1,
+							']' => { depth -= 1; if depth == 0 { break; } },
+							_ => {},
+						}
+					}
+					sc.mark(start, Kind::Attribute);
+				},
+				c if c.is_ascii_digit() => {
+					sc.number();
+					sc.mark(start, Kind::Number);
+				},
+				c if is_ident_start(c) => {
+					let word = sc.word();
+					let kind = match sc.peek() {
+						// byte and raw strings
+						Some('"') if matches!(word.as_str(), "b" | "r" | "br") => {
+							sc.pos += 1;
+							let closed = sc.string('"', word == "b");
+							sc.mark(start, Kind::String);
+							if !closed { return sc.done(State::Str('"')); }
+							continue;
+						},
+						Some('!') if sc.peek_at(1) != Some('=') => {
+							sc.pos += 1;
+							Some(Kind::Macro)
+						},
+						_ if RUST_KEYWORDS.contains(&word.as_str()) => Some(Kind::Keyword),
+						_ if matches!(word.as_str(), "true" | "false") => Some(Kind::Constant),
+						_ if RUST_TYPES.contains(&word.as_str()) => Some(Kind::Type),
+						_ if word.len() > 1 && word.chars().all(|c| c.is_uppercase() || c == '_' || c.is_ascii_digit()) => Some(Kind::Constant),
+						_ if word.starts_with(char::is_uppercase) => Some(Kind::Type),
+						Some('(') => Some(Kind::Function),
+						_ => Option::None,
+					};
+					if let Some(kind) = kind { sc.mark(start, kind); }
+				},
+				_ => sc.pos += 1,
+			}
+		}
+		sc.done(State::Normal)
+	}
+}
+
+/*
+* markdown
+*/
+struct Markdown;
+
+impl Language for Markdown {
+	fn name(&self) -> &'static str { "markdown" }
+	fn extensions(&self) -> &'static [&'static str] { &["md", "markdown"] }
+
+	fn line(&self, line: &str, state: State) -> (Vec<Span>, State) {
+		let mut sc = Scanner::new(line);
+		let trimmed = line.trim_start();
+
+		// code fences, the fence lines belong to the block
+		let fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+		if state == State::Fence || fence {
+			sc.skip_rest();
+			sc.mark(0, Kind::Code);
+			let open = (state == State::Fence) != fence;
+			return sc.done(if open { State::Fence } else { State::Normal });
+		}
+		if trimmed.starts_with('#') {
+			sc.skip_rest();
+			sc.mark(0, Kind::Heading);
+			return sc.done(State::Normal);
+		}
+		if trimmed.starts_with('>') {
+			sc.skip_rest();
+			sc.mark(0, Kind::Comment);
+			return sc.done(State::Normal);
+		}
+
+		// list markers
+		sc.eat_while(char::is_whitespace);
+		let start = sc.pos;
+		if matches!(sc.peek(), Some('-' | '*' | '+')) && sc.peek_at(1) == Some(' ') {
+			sc.pos += 1;
+		} else {
+			sc.eat_while(|c| c.is_ascii_digit());
+			if sc.pos == start || sc.peek() != Some('.') { sc.pos = start; } else { sc.pos += 1; }
+		}
+		sc.mark(start, Kind::Keyword);
+
+		while let Some(c) = sc.peek() {
+			let start = sc.pos;
+			match c {
+				'\\' => sc.pos += 2,
+				'`' => {
+					sc.pos += 1;
+					sc.string('`', false);
+					sc.mark(start, Kind::Code);
+				},
+				'*' | '_' => {
+					// `**strong**` or `*emphasis*`, only if it gets closed
+					let delim = if sc.peek_at(1) == Some(c) { 2 } else { 1 };
+					let close: String = std::iter::repeat_n(c, delim).collect();
+					sc.pos += delim;
+					let body = sc.pos;
+					while sc.peek().is_some() && !sc.starts_with(&close) { sc.pos += 1; }
+					if sc.peek().is_some() && sc.pos > body {
+						sc.pos += delim;
+						sc.mark(start, Kind::Emphasis);
+					} else {
+						sc.pos = body;
+					}
+				},
+				'[' => {
+					// `[text](url)`
+					sc.pos += 1;
+					sc.eat_while(|c| c != ']');
+					if sc.starts_with("](") {
+						sc.eat_while(|c| c != ')');
+						sc.pos = (sc.pos + 1).min(sc.chars.len());
+						sc.mark(start, Kind::Link);
+					} else {
+						sc.pos = start + 1;
+					}
+				},
+				_ => sc.pos += 1,
+			}
+		}
+		sc.pos = sc.pos.min(sc.chars.len());
+		sc.done(State::Normal)
+	}
+}
+
+/*
+* toml
+*/
+struct Toml;
+
+impl Language for Toml {
+	fn name(&self) -> &'static str { "toml" }
+	fn extensions(&self) -> &'static [&'static str] { &["toml", "Cargo.lock"] }
+
+	fn line(&self, line: &str, state: State) -> (Vec<Span>, State) {
+		let mut sc = Scanner::new(line);
+		if let State::LongStr(q) = state {
+			let triple: String = std::iter::repeat_n(q, 3).collect();
+			while sc.peek().is_some() && !sc.starts_with(&triple) {
+				sc.pos += if sc.peek() == Some('\\') && q == '"' { 2 } else { 1 };
+			}
+			let closed = sc.peek().is_some();
+			sc.pos = (sc.pos + 3).min(sc.chars.len());
+			sc.mark(0, Kind::String);
+			if !closed { return sc.done(state); }
+		} else {
+			sc.eat_while(char::is_whitespace);
+			let start = sc.pos;
+			match sc.peek() {
+				// [table] and [[array of tables]]
+				Some('[') => {
+					sc.eat_while(|c| c != '#');
+					while sc.pos > start && sc.chars[sc.pos - 1].is_whitespace() { sc.pos -= 1; }
+					sc.mark(start, Kind::Heading);
+				},
+				// key = value
+				Some(c) if c != '#' => {
+					while let Some(c) = sc.peek().filter(|c| *c != '=') {
+						sc.pos += 1;
+						if c == '"' || c == '\'' { sc.string(c, c == '"'); }
+					}
+					if sc.peek() == Some('=') {
+						sc.mark(start, Kind::Key);
+					} else {
+						sc.pos = start;
+					}
+				},
+				_ => {},
+			}
+		}
+
+		while let Some(c) = sc.peek() {
+			let start = sc.pos;
+			match c {
+				'#' => {
+					sc.skip_rest();
+					sc.mark(start, Kind::Comment);
+				},
+				'"' | '\'' if sc.peek_at(1) == Some(c) && sc.peek_at(2) == Some(c) => {
+					sc.pos += 3;
+					let triple: String = std::iter::repeat_n(c, 3).collect();
+					while sc.peek().is_some() && !sc.starts_with(&triple) { sc.pos += 1; }
+					let closed = sc.peek().is_some();
+					sc.pos = (sc.pos + 3).min(sc.chars.len());
+					sc.mark(start, Kind::String);
+					if !closed { return sc.done(State::LongStr(c)); }
+				},
+				'"' | '\'' => {
+					sc.pos += 1;
+					sc.string(c, c == '"');
+					sc.mark(start, Kind::String);
+				},
+				c if c.is_ascii_digit() || ((c == '-' || c == '+') && sc.peek_at(1).is_some_and(|c| c.is_ascii_digit())) => {
+					sc.pos += 1;
+					// dates and times too
+					sc.eat_while(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | ':' | '+'));
+					sc.mark(start, Kind::Number);
+				},
+				c if c.is_alphabetic() => {
+					let word = sc.word();
+					if matches!(word.as_str(), "true" | "false" | "inf" | "nan") {
+						sc.mark(start, Kind::Constant);
+					}
+				},
+				_ => sc.pos += 1,
+			}
+		}
+		sc.done(State::Normal)
+	}
+}
+
+/*
+* json
+*/
+struct Json;
+
+impl Language for Json {
+	fn name(&self) -> &'static str { "json" }
+	fn extensions(&self) -> &'static [&'static str] { &["json"] }
+
+	fn line(&self, line: &str, _state: State) -> (Vec<Span>, State) {
+		let mut sc = Scanner::new(line);
+		while let Some(c) = sc.peek() {
+			let start = sc.pos;
+			match c {
+				'"' => {
+					sc.pos += 1;
+					sc.string('"', true);
+					let end = sc.pos;
+					sc.eat_while(char::is_whitespace);
+					let kind = if sc.peek() == Some(':') { Kind::Key } else { Kind::String };
+					sc.pos = end;
+					sc.mark(start, kind);
+				},
+				c if c.is_ascii_digit() || c == '-' => {
+					sc.pos += 1;
+					sc.eat_while(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+					sc.mark(start, Kind::Number);
+				},
+				c if c.is_alphabetic() => {
+					let word = sc.word();
+					if matches!(word.as_str(), "true" | "false" | "null") {
+						sc.mark(start, Kind::Constant);
+					}
+				},
+				_ => sc.pos += 1,
+			}
+		}
+		sc.done(State::Normal)
+	}
+}
+
+/*
+* shell
+*/
+struct Shell;
+
+const SHELL_KEYWORDS: &[&str] = &[
+	"if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac",
+	"in", "function", "select", "return", "local", "export", "readonly", "declare", "break",
+	"continue",
+];
+
+impl Language for Shell {
+	fn name(&self) -> &'static str { "shell" }
+	fn extensions(&self) -> &'static [&'static str] {
+		&["sh", "bash", "zsh", ".bashrc", ".bash_profile", ".profile", ".zshrc"]
+	}
+
+	fn line(&self, line: &str, state: State) -> (Vec<Span>, State) {
+		let mut sc = Scanner::new(line);
+		if let State::Str(q) = state {
+			let closed = sc.string(q, q == '"');
+			sc.mark(0, Kind::String);
+			if !closed { return sc.done(state); }
+		}
+
+		while let Some(c) = sc.peek() {
+			let start = sc.pos;
+			let word_start = start == 0 || sc.chars[start - 1].is_whitespace();
+			match c {
+				'#' if word_start => {
+					sc.skip_rest();
+					sc.mark(start, Kind::Comment);
+				},
+				'\\' => sc.pos += 2,
+				'"' | '\'' => {
+					sc.pos += 1;
+					let closed = sc.string(c, c == '"');
+					sc.mark(start, Kind::String);
+					if !closed { return sc.done(State::Str(c)); }
+				},
+				'$' => {
+					sc.pos += 1;
+					match sc.peek() {
+						Some('{') => { sc.eat_while(|c| c != '}'); sc.pos += 1; },
+						Some(c) if is_ident_start(c) => { sc.word(); },
+						Some(c) if c.is_ascii_digit() || "@*#?$!-".contains(c) => sc.pos += 1,
+						_ => {},
+					}
+					sc.pos = sc.pos.min(sc.chars.len());
+					sc.mark(start, Kind::Variable);
+				},
+				c if c.is_ascii_digit() && word_start => {
+					sc.eat_while(|c| c.is_ascii_digit());
+					sc.mark(start, Kind::Number);
+				},
+				c if is_ident_start(c) => {
+					let word = sc.word();
+					if SHELL_KEYWORDS.contains(&word.as_str()) && word_start {
+						sc.mark(start, Kind::Keyword);
+					} else if sc.starts_with("()") {
+						sc.mark(start, Kind::Function);
+					}
+				},
+				_ => sc.pos += 1,
+			}
+		}
+		sc.pos = sc.pos.min(sc.chars.len());
+		sc.done(State::Normal)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// the highlighted text of a line with its kind
+	fn kinds(lang: &dyn Language, line: &str, state: State) -> (Vec<(String, Kind)>, State) {
+		let chars: Vec<char> = line.chars().collect();
+		let (spans, state) = lang.line(line, state);
+		let kinds = spans.into_iter()
+			.map(|s| (chars[s.range].iter().collect(), s.kind))
+			.collect();
+		(kinds, state)
+	}
+
+	fn text(kinds: &[(String, Kind)], kind: Kind) -> Vec<&str> {
+		kinds.iter().filter(|(_, k)| *k == kind).map(|(t, _)| t.as_str()).collect()
+	}
+
+	#[test]
+	fn rust_test() {
+		let (k, state) = kinds(&Rust, r#"pub fn main() -> u8 { println!("hi \"x\""); 'a' } // end"#, State::Normal);
+		assert_eq!(vec!["pub", "fn"], text(&k, Kind::Keyword));
+		assert_eq!(vec!["main"], text(&k, Kind::Function));
+		assert_eq!(vec!["u8"], text(&k, Kind::Type));
+		assert_eq!(vec!["println!"], text(&k, Kind::Macro));
+		assert_eq!(vec![r#""hi \"x\"""#, "'a'"], text(&k, Kind::String));
+		assert_eq!(vec!["// end"], text(&k, Kind::Comment));
+		assert_eq!(State::Normal, state);
+
+		let (k, state) = kinds(&Rust, "let x = 1; /* a /* b */", State::Normal);
+		assert_eq!(vec!["/* a /* b */"], text(&k, Kind::Comment));
+		assert_eq!(State::Comment(1), state);
+		let (k, state) = kinds(&Rust, "still */ 0x1f", state);
+		assert_eq!(vec!["still */"], text(&k, Kind::Comment));
+		assert_eq!(vec!["0x1f"], text(&k, Kind::Number));
+		assert_eq!(State::Normal, state);
+
+		let (k, _) = kinds(&Rust, "fn f<'a>(x: &'a Foo) {}", State::Normal);
+		assert_eq!(vec!["'a", "'a", "Foo"], text(&k, Kind::Type));
+	}
+
+	#[test]
+	fn markdown_test() {
+		let (k, _) = kinds(&Markdown, "# title", State::Normal);
+		assert_eq!(vec!["# title"], text(&k, Kind::Heading));
+		let (k, _) = kinds(&Markdown, "- use `w` and **bold** [here](http://x) 2*3", State::Normal);
+		assert_eq!(vec!["-"], text(&k, Kind::Keyword));
+		assert_eq!(vec!["`w`"], text(&k, Kind::Code));
+		assert_eq!(vec!["**bold**"], text(&k, Kind::Emphasis));
+		assert_eq!(vec!["[here](http://x)"], text(&k, Kind::Link));
+
+		let (_, state) = kinds(&Markdown, "```rust", State::Normal);
+		assert_eq!(State::Fence, state);
+		let (k, state) = kinds(&Markdown, "# not a heading", state);
+		assert_eq!(vec!["# not a heading"], text(&k, Kind::Code));
+		let (_, state) = kinds(&Markdown, "```", state);
+		assert_eq!(State::Normal, state);
+	}
+
+	#[test]
+	fn toml_json_shell_test() {
+		let (k, _) = kinds(&Toml, r#"name = "xi" # the name"#, State::Normal);
+		assert_eq!(vec!["name "], text(&k, Kind::Key));
+		assert_eq!(vec![r#""xi""#], text(&k, Kind::String));
+		assert_eq!(vec!["# the name"], text(&k, Kind::Comment));
+		let (k, _) = kinds(&Toml, "[dependencies] # deps", State::Normal);
+		assert_eq!(vec!["[dependencies]"], text(&k, Kind::Heading));
+		let (_, state) = kinds(&Toml, r#"text = """"#, State::Normal);
+		assert_eq!(State::LongStr('"'), state);
+		let (k, state) = kinds(&Toml, r#"more""" x = 1"#, state);
+		assert_eq!(vec![r#"more""""#], text(&k, Kind::String));
+		assert_eq!(State::Normal, state);
+
+		let (k, _) = kinds(&Json, r#"{"a": [1.5, "b", true, null]}"#, State::Normal);
+		assert_eq!(vec![r#""a""#], text(&k, Kind::Key));
+		assert_eq!(vec![r#""b""#], text(&k, Kind::String));
+		assert_eq!(vec!["1.5"], text(&k, Kind::Number));
+		assert_eq!(vec!["true", "null"], text(&k, Kind::Constant));
+
+		let (k, state) = kinds(&Shell, r#"if [ "$x" ]; then echo ${HOME}#no $1 # yes"#, State::Normal);
+		assert_eq!(vec!["if", "then"], text(&k, Kind::Keyword));
+		assert_eq!(vec!["${HOME}", "$1"], text(&k, Kind::Variable));
+		assert_eq!(vec!["# yes"], text(&k, Kind::Comment));
+		assert_eq!(State::Normal, state);
+		let (_, state) = kinds(&Shell, "echo 'multi", State::Normal);
+		assert_eq!(State::Str('\''), state);
+	}
+
+	#[test]
+	fn for_file_test() {
+		assert_eq!("rust", for_file("src/main.rs").unwrap().name());
+		assert_eq!("toml", for_file("Cargo.lock").unwrap().name());
+		assert_eq!("shell", for_file("/home/me/.bashrc").unwrap().name());
+		assert!(for_file("notes.txt").is_none());
+		assert!(by_name("json").is_some());
+	}
+
+	#[test]
+	fn highlighter_test() {
+		let mut rope = ropey::Rope::from("```\n# a\n```\n# b");
+		let mut h = Highlighter::new(&Markdown);
+		h.update(&rope, 1);
+		assert_eq!(2, h.spans.len(), "only what was asked for");
+		assert_eq!(Kind::Code, h.spans(1)[0].kind);
+
+		// dropping the fence changes the lines after it
+		h.edit(&rope, 0, 3, "");
+		rope.remove(0..3);
+		h.update(&rope, 10);
+		assert_eq!(Kind::Heading, h.spans(1)[0].kind);
+		assert_eq!(4, h.spans.len());
+		assert!(h.spans(9).is_empty());
+	}
+
+	#[test]
+	fn tree_highlighter_test() {
+		let mut rope = ropey::Rope::from("a /* b\nc\nd */ e\nf");
+		let mut h = Highlighter::new(&Rust);
+		h.update(&rope, 1);
+		assert_eq!(2, h.spans.len(), "only what was asked for");
+		assert_eq!(Kind::Comment, h.spans(1)[0].kind);
+
+		// closing the comment early changes the lines after it
+		h.edit(&rope, 6, 0, "*/");
+		rope.insert(6, "*/");
+		h.update(&rope, 10);
+		assert!(h.spans(1).is_empty());
+		assert_eq!(4, h.spans.len());
+	}
+}
@@ -0,0 +1,123 @@
+/*
+* tab pages - each tab has its own windows and layout. the active tab lives
+* in the editor (Editor::windows and friends), its place in Editor::tabs
+* is left empty until another tab gets active.
+*/
+use crate::window::{Layout, Window};
+use crate::Editor;
+
+#[derive(Default)]
+pub struct Tab {
+	windows: Vec<Window>,
+	layout: Layout,
+	active_win: usize,
+}
+
+impl Editor {
+	/// rows the tab bar takes, it only shows up with more than one tab
+	pub fn tabline(&self) -> u16 {
+		if self.tabs.len() > 1 { 1 } else { 0 }
+	}
+
+	/// puts the windows of the editor and those of tab t in each other's place
+	fn swap_tab(&mut self, t: usize) {
+		let tab = &mut self.tabs[t];
+		std::mem::swap(&mut self.windows, &mut tab.windows);
+		std::mem::swap(&mut self.layout, &mut tab.layout);
+		std::mem::swap(&mut self.active_win, &mut tab.active_win);
+	}
+
+	/// every window of every tab
+	pub fn all_windows_mut(&mut self) -> impl Iterator<Item = &mut Window> {
+		self.windows.iter_mut().chain(self.tabs.iter_mut().flat_map(|t| t.windows.iter_mut()))
+	}
+
+	/// makes tab t the active one
+	pub fn goto_tab(&mut self, t: usize) {
+		if t == self.active_tab || t >= self.tabs.len() { return; }
+		self.stash_view();
+		self.swap_tab(self.active_tab);
+		self.active_tab = t;
+		self.swap_tab(t);
+		self.load_view();
+	}
+
+	/// opens a tab after the active one, with a single window on the active buffer
+	pub fn tab_new(&mut self) {
+		let tab = Tab {
+			windows: vec![Window { buf: self.active_buf, view: Option::None }],
+			..Default::default()
+		};
+		self.tabs.insert(self.active_tab + 1, tab);
+		self.goto_tab(self.active_tab + 1);
+	}
+
+	/// closes the active tab, its buffers stay open
+	pub fn tab_close(&mut self) -> Result<(), String> {
+		if self.tabs.len() == 1 { return Err("cant close the last tab".to_owned()); }
+		let t = self.active_tab;
+		self.goto_tab(if t + 1 < self.tabs.len() { t + 1 } else { t - 1 });
+		self.tabs.remove(t);
+		if self.active_tab > t { self.active_tab -= 1; }
+		Ok(())
+	}
+
+	/// puts the active tab at position to, 0 is the first
+	pub fn tab_move(&mut self, to: usize) {
+		let to = to.min(self.tabs.len() - 1);
+		let tab = self.tabs.remove(self.active_tab);
+		self.tabs.insert(to, tab);
+		self.active_tab = to;
+	}
+
+	/// what the tab bar says about every tab: the name of the buffer in its active window
+	pub fn tab_labels(&self) -> Vec<String> {
+		(0..self.tabs.len())
+			.map(|t| {
+				let (windows, active) = match t == self.active_tab {
+					true => (&self.windows, self.active_win),
+					false => (&self.tabs[t].windows, self.tabs[t].active_win),
+				};
+				let buf = &self.bufs[windows[active].buf];
+				let modified = if buf.is_modified() { "*" } else { "" };
+				match windows.len() {
+					1 => format!("{} {}{}", t + 1, buf.filename, modified),
+					n => format!("{} {}{} ({})", t + 1, buf.filename, modified, n),
+				}
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::buffer::Buffer;
+	use crate::window::Split;
+	use crate::Editor;
+
+	fn editor() -> Editor {
+		let mut ed = Editor::default();
+		ed.bufs.push(Buffer::open("a".to_owned(), ropey::Rope::from("abc"), 20, 5));
+		ed.bufs.push(Buffer::open("b".to_owned(), ropey::Rope::from("def"), 20, 5));
+		ed
+	}
+
+	#[test]
+	fn tabs_test() {
+		let mut ed = editor();
+		ed.split(Split::Vertical).unwrap();
+		ed.tab_new();
+		ed.show_buf(1);
+		assert_eq!((1, 1, 1), (ed.active_tab, ed.windows.len(), ed.tabline()));
+		assert_eq!(vec!["1 a (2)", "2 b"], ed.tab_labels());
+
+		ed.goto_tab(0);
+		assert_eq!((2, 0), (ed.windows.len(), ed.active_buf));
+		ed.tab_move(1);
+		assert_eq!(vec!["1 b", "2 a (2)"], ed.tab_labels());
+
+		ed.tab_close().unwrap();
+		assert_eq!((0, 1, 0), (ed.active_tab, ed.active_buf, ed.tabline()));
+		assert!(ed.tab_close().is_err());
+	}
+}
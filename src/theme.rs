@@ -0,0 +1,234 @@
+/*
+* themes - every color on screen comes from a named scope of the theme.
+* a few themes are built in (see themes/), others are toml files in the
+* themes dir, `$XDG_CONFIG_HOME/xi/themes` or `~/.config/xi/themes`:
+*
+*   inherits = "default"
+*   keyword = "red"
+*   selection = { fg = "black", bg = "#fabd2f", bold = true }
+*
+* scopes a theme leaves out come from the one it inherits, or are left plain.
+*/
+use crate::syntax::Kind;
+use ratatui::style::{Color, Modifier, Style};
+use std::{collections::HashMap, path::PathBuf};
+
+/// something on screen that has its own colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+	/// the text of the buffer, and the background around it
+	Text,
+	Selection,
+	/// text just changed by undo/redo
+	Flash,
+	/// matches of a search, `hf` in a hex view
+	Search,
+	LineNumber,
+	Border,
+	Title,
+	Prompt,
+	/// the status line under the buffer
+	Status,
+	/// list glyphs, and spaces at the end of a line
+	Whitespace,
+	Trailing,
+	/// the tab bar, and the active tab in it
+	TabLine,
+	TabSel,
+	Syntax(Kind),
+}
+
+/// scopes by the name used in theme files
+const SCOPES: &[(&str, Scope)] = &[
+	("text", Scope::Text),
+	("selection", Scope::Selection),
+	("flash", Scope::Flash),
+	("search", Scope::Search),
+	("linenr", Scope::LineNumber),
+	("border", Scope::Border),
+	("title", Scope::Title),
+	("prompt", Scope::Prompt),
+	("status", Scope::Status),
+	("whitespace", Scope::Whitespace),
+	("trailing", Scope::Trailing),
+	("tabline", Scope::TabLine),
+	("tabsel", Scope::TabSel),
+	("keyword", Scope::Syntax(Kind::Keyword)),
+	("type", Scope::Syntax(Kind::Type)),
+	("function", Scope::Syntax(Kind::Function)),
+	("string", Scope::Syntax(Kind::String)),
+	("number", Scope::Syntax(Kind::Number)),
+	("comment", Scope::Syntax(Kind::Comment)),
+	("constant", Scope::Syntax(Kind::Constant)),
+	("macro", Scope::Syntax(Kind::Macro)),
+	("attribute", Scope::Syntax(Kind::Attribute)),
+	("key", Scope::Syntax(Kind::Key)),
+	("variable", Scope::Syntax(Kind::Variable)),
+	("heading", Scope::Syntax(Kind::Heading)),
+	("emphasis", Scope::Syntax(Kind::Emphasis)),
+	("code", Scope::Syntax(Kind::Code)),
+	("link", Scope::Syntax(Kind::Link)),
+];
+
+const BUILTIN: &[(&str, &str)] = &[
+	("default", include_str!("../themes/default.toml")),
+	("light", include_str!("../themes/light.toml")),
+	("gruvbox", include_str!("../themes/gruvbox.toml")),
+];
+
+#[derive(Debug)]
+pub struct Theme {
+	pub name: String,
+	styles: HashMap<Scope, Style>,
+}
+
+impl Theme {
+	/// the style of scope, plain if the theme doesnt say
+	pub fn style(&self, scope: Scope) -> Style {
+		self.styles.get(&scope).copied().unwrap_or_default()
+	}
+
+	/// a built in theme, or the file `name.toml` in the themes dir.
+	/// anything that looks like a path is read as is.
+	pub fn load(name: &str) -> Result<Theme, String> {
+		if let Some(theme) = builtin(name) { return theme; }
+		let path = match name.ends_with(".toml") || name.contains('/') {
+			true => PathBuf::from(name),
+			false => theme_dir()
+				.ok_or("no themes dir, HOME isnt set".to_owned())?
+				.join(format!("{}.toml", name)),
+		};
+		let src = std::fs::read_to_string(&path)
+			.map_err(|e| format!("no theme {}: {}", name, e))?;
+		let name = path.file_stem().map_or(name.into(), |s| s.to_string_lossy());
+		Theme::parse(&name, &src)
+	}
+
+	/// reads a theme file, the error says what is wrong with it
+	pub fn parse(name: &str, src: &str) -> Result<Theme, String> {
+		let table: toml::Table = src.parse().map_err(|e: toml::de::Error| e.message().to_owned())?;
+		let mut theme = match table.get("inherits") {
+			Option::None => Theme { name: String::new(), styles: HashMap::new() },
+			// only built in ones, so a file cant inherit from itself
+			Some(toml::Value::String(base)) => builtin(base)
+				.ok_or(format!("cant inherit from {}, only from built in themes", base))??,
+			Some(_) => return Err("inherits has to be the name of a theme".to_owned()),
+		};
+		theme.name = name.to_owned();
+
+		for (key, value) in table.iter().filter(|(k, _)| *k != "inherits") {
+			let scope = SCOPES.iter()
+				.find(|(n, _)| n == key)
+				.ok_or(format!("unknown scope: {}", key))?.1;
+			let style = parse_style(value).map_err(|e| format!("{}: {}", key, e))?;
+			theme.styles.insert(scope, style);
+		}
+		Ok(theme)
+	}
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		builtin("default").and_then(Result::ok).expect("the default theme is broken")
+	}
+}
+
+fn builtin(name: &str) -> Option<Result<Theme, String>> {
+	let (name, src) = BUILTIN.iter().find(|(n, _)| *n == name)?;
+	Some(Theme::parse(name, src))
+}
+
+/// names of the built in themes
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+	BUILTIN.iter().map(|(n, _)| *n)
+}
+
+/// `$XDG_CONFIG_HOME/xi/themes`, or the same under `~/.config`
+fn theme_dir() -> Option<PathBuf> {
+	let config = std::env::var_os("XDG_CONFIG_HOME")
+		.filter(|v| !v.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+	Some(config.join("xi/themes"))
+}
+
+/// `"red"` for the text color or `{ fg = "red", bg = "black", bold = true }`
+fn parse_style(value: &toml::Value) -> Result<Style, String> {
+	let table = match value {
+		toml::Value::String(fg) => return Ok(Style::new().fg(parse_color(fg)?)),
+		toml::Value::Table(table) => table,
+		_ => return Err("expected a color or a table".to_owned()),
+	};
+	let mut style = Style::new();
+	for (key, value) in table {
+		match (key.as_str(), value) {
+			("fg", toml::Value::String(c)) => style = style.fg(parse_color(c)?),
+			("bg", toml::Value::String(c)) => style = style.bg(parse_color(c)?),
+			(modifier, toml::Value::Boolean(on)) => {
+				let m = match modifier {
+					"bold" => Modifier::BOLD,
+					"italic" => Modifier::ITALIC,
+					"underlined" => Modifier::UNDERLINED,
+					"dim" => Modifier::DIM,
+					"reversed" => Modifier::REVERSED,
+					_ => return Err(format!("unknown modifier: {}", modifier)),
+				};
+				style = if *on { style.add_modifier(m) } else { style.remove_modifier(m) };
+			},
+			_ => return Err(format!("invalid {}: {}", key, value)),
+		}
+	}
+	Ok(style)
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+	s.parse().map_err(|_| format!("invalid color: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::scratch::Scratch;
+
+	#[test]
+	fn builtin_test() {
+		for name in builtin_names() {
+			let theme = Theme::load(name).unwrap();
+			assert_eq!(name, theme.name);
+			assert_ne!(Style::default(), theme.style(Scope::Selection), "{}", name);
+			assert_ne!(Style::default(), theme.style(Scope::Search), "{}", name);
+			assert_ne!(Style::default(), theme.style(Scope::Syntax(Kind::Keyword)), "{}", name);
+		}
+	}
+
+	#[test]
+	fn parse_test() {
+		let theme = Theme::parse("mine", r##"
+			inherits = "default"
+			keyword = "#ff0000"
+			selection = { bg = "blue", bold = true }
+		"##).unwrap();
+		assert_eq!("mine", theme.name);
+		assert_eq!(Style::new().fg(Color::Rgb(255, 0, 0)), theme.style(Scope::Syntax(Kind::Keyword)));
+		assert_eq!(Style::new().bg(Color::Blue).add_modifier(Modifier::BOLD), theme.style(Scope::Selection));
+		assert_eq!(Style::new().fg(Color::Green), theme.style(Scope::Syntax(Kind::String)), "inherited");
+		assert_eq!(Style::default(), Theme::parse("x", "").unwrap().style(Scope::Selection));
+
+		assert!(Theme::parse("x", "kword = \"red\"").unwrap_err().contains("kword"));
+		assert!(Theme::parse("x", "keyword = \"reddish\"").unwrap_err().contains("reddish"));
+		assert!(Theme::parse("x", "keyword = { fg = \"red\", blink = true }").is_err());
+		assert!(Theme::parse("x", "inherits = \"x\"").is_err());
+		assert!(Theme::parse("x", "keyword = ").is_err());
+	}
+
+	#[test]
+	fn load_file_test() {
+		let dir = Scratch::new("theme");
+		let path = dir.join("dusk.toml");
+		std::fs::write(&path, "comment = \"gray\"").unwrap();
+		let theme = Theme::load(&path.to_string_lossy()).unwrap();
+		assert_eq!("dusk", theme.name);
+		assert_eq!(Style::new().fg(Color::Gray), theme.style(Scope::Syntax(Kind::Comment)));
+		assert!(Theme::load(&dir.join("nope.toml").to_string_lossy()).is_err());
+	}
+}
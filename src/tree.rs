@@ -0,0 +1,346 @@
+/*
+* syntax trees - languages with a bundled tree-sitter grammar get parsed into
+* a tree. edits are given to the old tree before the rope changes, so the next
+* parse only redoes the part they touched.
+*
+* the tree drives the highlighting (through the highlights query of the
+* grammar), `]f`/`[f`, the function/class/argument text objects and growing
+* the selection node by node. everything going in and out is in chars,
+* tree-sitter itself works in bytes.
+*/
+use crate::syntax::{Kind, Span};
+use ropey::Rope;
+use std::ops::Range;
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree};
+
+/// what a grammar knows about a language
+pub struct Grammar {
+	language: Language,
+	highlights: &'static str,
+	/// node kinds for the text objects
+	functions: &'static [&'static str],
+	classes: &'static [&'static str],
+	/// nodes whose children are the arguments
+	lists: &'static [&'static str],
+}
+
+/// the grammar for a language of syntax.rs, by its name
+pub fn grammar(name: &str) -> Option<Grammar> {
+	Some(match name {
+		"rust" => Grammar {
+			language: tree_sitter_rust::LANGUAGE.into(),
+			highlights: tree_sitter_rust::HIGHLIGHTS_QUERY,
+			functions: &["function_item", "function_signature_item", "closure_expression"],
+			classes: &["struct_item", "enum_item", "union_item", "impl_item", "trait_item"],
+			lists: &["parameters", "arguments", "type_arguments", "type_parameters", "tuple_expression"],
+		},
+		"shell" => Grammar {
+			language: tree_sitter_bash::LANGUAGE.into(),
+			highlights: tree_sitter_bash::HIGHLIGHT_QUERY,
+			functions: &["function_definition"],
+			classes: &[],
+			lists: &["command"],
+		},
+		"json" => Grammar {
+			language: tree_sitter_json::LANGUAGE.into(),
+			highlights: tree_sitter_json::HIGHLIGHTS_QUERY,
+			functions: &[],
+			classes: &["object"],
+			lists: &["object", "array"],
+		},
+		"toml" => Grammar {
+			language: tree_sitter_toml_ng::LANGUAGE.into(),
+			highlights: tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+			functions: &[],
+			classes: &["table", "table_array_element"],
+			lists: &["table", "inline_table", "array"],
+		},
+		_ => return Option::None,
+	})
+}
+
+/// what `af`, `ac` and `aa` select
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Object {
+	Function,
+	Class,
+	Argument,
+}
+
+pub struct SyntaxTree {
+	grammar: Grammar,
+	parser: Parser,
+	query: Query,
+	tree: Option<Tree>,
+	/// edited since the last parse
+	dirty: bool,
+}
+
+impl SyntaxTree {
+	/// None if the grammar doesnt load, which would be a bug
+	pub fn new(grammar: Grammar) -> Option<Self> {
+		let mut parser = Parser::new();
+		parser.set_language(&grammar.language).ok()?;
+		let query = Query::new(&grammar.language, grammar.highlights).ok()?;
+		Some(SyntaxTree { grammar, parser, query, tree: Option::None, dirty: true })
+	}
+
+	/// the text is about to change at char at: removed chars go, inserted comes in
+	pub fn edit(&mut self, rope: &Rope, at: usize, removed: usize, inserted: &str) {
+		self.dirty = true;
+		let Some(tree) = &mut self.tree else { return; };
+		let start_byte = rope.char_to_byte(at);
+		let old_end_byte = rope.char_to_byte(at + removed);
+		let start_position = point(rope, start_byte);
+		let new_end_position = match inserted.rfind('\n') {
+			Some(i) => Point::new(start_position.row + inserted.matches('\n').count(), inserted.len() - i - 1),
+			Option::None => Point::new(start_position.row, start_position.column + inserted.len()),
+		};
+		tree.edit(&InputEdit {
+			start_byte,
+			old_end_byte,
+			new_end_byte: start_byte + inserted.len(),
+			start_position,
+			old_end_position: point(rope, old_end_byte),
+			new_end_position,
+		});
+	}
+
+	/// parses the text if it was edited.
+	/// returns the first line whose highlighting could have changed.
+	pub fn parse(&mut self, rope: &Rope) -> Option<usize> {
+		if !self.dirty { return Option::None; }
+		let new = self.parser.parse_with_options(&mut |byte, _| chunk(rope, byte), self.tree.as_ref(), Option::None)?;
+		let first = match &self.tree {
+			Some(old) => old.changed_ranges(&new).map(|r| r.start_byte).min(),
+			Option::None => Some(0),
+		};
+		self.tree = Some(new);
+		self.dirty = false;
+		first.map(|b| rope.byte_to_line(b.min(rope.len_bytes())))
+	}
+
+	/// spans of each line in lines, like the tokenizers of syntax.rs make them
+	pub fn highlight(&self, rope: &Rope, lines: Range<usize>) -> Vec<Vec<Span>> {
+		let mut out = vec![vec![]; lines.len()];
+		let Some(tree) = &self.tree else { return out; };
+		let bytes = rope.line_to_byte(lines.start)..rope.line_to_byte(lines.end);
+		let mut cursor = QueryCursor::new();
+		cursor.set_byte_range(bytes.clone());
+		let text = |node: Node| rope.byte_slice(node.byte_range()).chunks().map(str::as_bytes);
+		let mut captures = cursor.captures(&self.query, tree.root_node(), text);
+
+		let mut prev = Option::None;
+		while let Some((m, i)) = captures.next() {
+			let capture = m.captures[*i];
+			let range = capture.node.byte_range();
+			// when patterns match the same node the first one wins
+			if prev.as_ref() == Some(&range) { continue; }
+			prev = Some(range.clone());
+			let Some(kind) = kind(self.query.capture_names()[capture.index as usize]) else { continue; };
+
+			let (start, end) = (range.start.max(bytes.start), range.end.min(bytes.end));
+			if start >= end { continue; }
+			for line in rope.byte_to_line(start)..=rope.byte_to_line(end - 1) {
+				let line_start = rope.line_to_char(line);
+				let text_end = line_start + rope.line(line).chars().take_while(|c| *c != '\n').count();
+				let s = rope.byte_to_char(start).max(line_start);
+				let e = rope.byte_to_char(end).min(text_end);
+				if s >= e { continue; }
+				let spans = &mut out[line - lines.start];
+				let range = s - line_start..e - line_start;
+				// like `println` and `!`, which are captured one at a time
+				match spans.last_mut() {
+					Some(last) if last.kind == kind && last.range.end == range.start => last.range.end = range.end,
+					_ => spans.push(Span { range, kind }),
+				}
+			}
+		}
+		out
+	}
+
+	/// start of the closest function after (or before) char cs
+	pub fn function_jump(&self, rope: &Rope, cs: usize, forward: bool) -> Option<usize> {
+		let tree = self.tree.as_ref()?;
+		let at = rope.char_to_byte(cs);
+		let mut starts = vec![];
+		let mut cursor = tree.walk();
+		'walk: loop {
+			let node = cursor.node();
+			if self.grammar.functions.contains(&node.kind()) { starts.push(node.start_byte()); }
+			if cursor.goto_first_child() { continue; }
+			while !cursor.goto_next_sibling() {
+				if !cursor.goto_parent() { break 'walk; }
+			}
+		}
+		let byte = match forward {
+			true => starts.into_iter().filter(|b| *b > at).min(),
+			false => starts.into_iter().filter(|b| *b < at).max(),
+		}?;
+		Some(rope.byte_to_char(byte))
+	}
+
+	/// the smallest obj around the chars of range, and bigger than it
+	pub fn object(&self, rope: &Rope, range: Range<usize>, obj: Object) -> Option<Range<usize>> {
+		let mut node = self.node_at(rope, &range)?;
+		loop {
+			let found = match obj {
+				Object::Function => self.grammar.functions.contains(&node.kind()),
+				Object::Class => self.grammar.classes.contains(&node.kind()),
+				Object::Argument => node.is_named() && node.parent()
+					.is_some_and(|p| self.grammar.lists.contains(&p.kind())),
+			};
+			let around = chars(rope, node.byte_range());
+			if found && around != range { return Some(around); }
+			node = node.parent()?;
+		}
+	}
+
+	/// the smallest node around the chars of range that is bigger than range
+	pub fn expand(&self, rope: &Rope, range: Range<usize>) -> Option<Range<usize>> {
+		let mut node = self.node_at(rope, &range)?;
+		while chars(rope, node.byte_range()) == range {
+			node = node.parent()?;
+		}
+		Some(chars(rope, node.byte_range()))
+	}
+
+	fn node_at(&self, rope: &Rope, range: &Range<usize>) -> Option<Node<'_>> {
+		let root = self.tree.as_ref()?.root_node();
+		root.named_descendant_for_byte_range(rope.char_to_byte(range.start), rope.char_to_byte(range.end))
+	}
+}
+
+/// the text from byte on, for the parser
+fn chunk(rope: &Rope, byte: usize) -> &[u8] {
+	if byte >= rope.len_bytes() { return &[]; }
+	let (chunk, start, _, _) = rope.chunk_at_byte(byte);
+	&chunk.as_bytes()[byte - start..]
+}
+
+fn point(rope: &Rope, byte: usize) -> Point {
+	let row = rope.byte_to_line(byte);
+	Point::new(row, byte - rope.line_to_byte(row))
+}
+
+fn chars(rope: &Rope, bytes: Range<usize>) -> Range<usize> {
+	rope.byte_to_char(bytes.start)..rope.byte_to_char(bytes.end)
+}
+
+/// the kind of a capture of a highlights query, like `function.macro`
+fn kind(capture: &str) -> Option<Kind> {
+	Some(match capture {
+		"function.macro" => Kind::Macro,
+		"string.special.key" | "property" => Kind::Key,
+		_ => match capture.split('.').next()? {
+			"keyword" => Kind::Keyword,
+			"type" | "constructor" => Kind::Type,
+			"function" => Kind::Function,
+			"string" | "escape" => Kind::String,
+			"number" => Kind::Number,
+			"comment" => Kind::Comment,
+			"constant" | "boolean" | "label" => Kind::Constant,
+			"attribute" => Kind::Attribute,
+			"variable" => Kind::Variable,
+			_ => return Option::None,
+		},
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parsed(lang: &str, text: &str) -> (SyntaxTree, Rope) {
+		let rope = Rope::from(text);
+		let mut t = SyntaxTree::new(grammar(lang).unwrap()).unwrap();
+		assert_eq!(Some(0), t.parse(&rope));
+		(t, rope)
+	}
+
+	/// the highlighted text of kind on each line
+	fn text(t: &SyntaxTree, rope: &Rope, kind: Kind) -> Vec<String> {
+		let spans = t.highlight(rope, 0..rope.len_lines());
+		spans.iter().enumerate()
+			.flat_map(|(n, spans)| spans.iter()
+				.filter(|s| s.kind == kind)
+				.map(move |s| rope.line(n).slice(s.range.clone()).to_string()))
+			.collect()
+	}
+
+	#[test]
+	fn grammars_test() {
+		for lang in ["rust", "shell", "json", "toml"] {
+			assert!(grammar(lang).and_then(SyntaxTree::new).is_some(), "{}", lang);
+		}
+		assert!(grammar("markdown").is_none());
+	}
+
+	#[test]
+	fn highlight_test() {
+		let (t, rope) = parsed("rust", "/* a\nb */ fn main() {\n\tprintln!(\"x\");\n}");
+		assert_eq!(vec!["/* a", "b */"], text(&t, &rope, Kind::Comment));
+		assert_eq!(vec!["fn"], text(&t, &rope, Kind::Keyword));
+		assert_eq!(vec!["main"], text(&t, &rope, Kind::Function));
+		assert_eq!(vec!["println!"], text(&t, &rope, Kind::Macro));
+		assert_eq!(vec!["\"x\""], text(&t, &rope, Kind::String));
+
+		let (t, rope) = parsed("json", r#"{"a": [1, true]}"#);
+		assert_eq!(vec![r#""a""#], text(&t, &rope, Kind::Key));
+		assert_eq!(vec!["1"], text(&t, &rope, Kind::Number));
+	}
+
+	#[test]
+	fn edit_test() {
+		let (mut t, mut rope) = parsed("rust", "fn a() {}\nfn b() {}\n");
+		// opening a comment on the first line changes the second one too
+		t.edit(&rope, 0, 0, "/*");
+		rope.insert(0, "/*");
+		assert_eq!(Some(0), t.parse(&rope));
+		assert_eq!(None, t.parse(&rope), "nothing new to parse");
+		assert_eq!(2, text(&t, &rope, Kind::Comment).len());
+
+		t.edit(&rope, 0, 2, "");
+		rope.remove(0..2);
+		t.parse(&rope);
+		assert!(text(&t, &rope, Kind::Comment).is_empty());
+		assert_eq!(vec!["a", "b"], text(&t, &rope, Kind::Function));
+	}
+
+	#[test]
+	fn objects_test() {
+		let src = "struct S;\nfn a(x: u8, y: u8) {}\nfn b() { a(1, 2) }\n";
+		let (t, rope) = parsed("rust", src);
+		let at = |s: &str| src.find(s).unwrap();
+		let slice = |r: Range<usize>| src[r].to_owned();
+
+		assert_eq!(Some(at("fn a")), t.function_jump(&rope, 0, true));
+		assert_eq!(Some(at("fn b")), t.function_jump(&rope, at("fn a"), true));
+		assert_eq!(None, t.function_jump(&rope, at("fn b"), true));
+		assert_eq!(Some(at("fn a")), t.function_jump(&rope, at("fn b"), false));
+
+		let y = at("y:");
+		assert_eq!(Some("y: u8".to_owned()), t.object(&rope, y..y, Object::Argument).map(slice));
+		assert_eq!(Some("fn a(x: u8, y: u8) {}".to_owned()), t.object(&rope, y..y, Object::Function).map(slice));
+		assert_eq!(Some("struct S;".to_owned()), t.object(&rope, 1..1, Object::Class).map(slice));
+		assert_eq!(None, t.object(&rope, y..y, Object::Class));
+		let f = at("fn a")..at("\nfn b");
+		assert_eq!(None, t.object(&rope, f, Object::Function), "nothing bigger");
+		let two = at("2)");
+		assert_eq!(Some("2".to_owned()), t.object(&rope, two..two, Object::Argument).map(slice));
+	}
+
+	#[test]
+	fn expand_test() {
+		let src = "fn a() { f(1 + 2) }";
+		let (t, rope) = parsed("rust", src);
+		let one = src.find('1').unwrap();
+		let mut steps = vec![];
+		let mut range = one..one;
+		while let Some(r) = t.expand(&rope, range) {
+			steps.push(src[r.clone()].to_owned());
+			range = r;
+		}
+		assert_eq!(vec!["1", "1 + 2", "(1 + 2)", "f(1 + 2)", "{ f(1 + 2) }", src], steps);
+	}
+}
@@ -0,0 +1,79 @@
+/*
+* undo files - the undo history of a file is saved in the undo dir every
+* time the file is written, so undo still works after reopening it.
+* the file's content hash goes along, a history for different text is useless.
+*/
+use crate::{file, history::History};
+use std::{fs, io::{self, BufRead}, path::{Path, PathBuf}};
+
+const MAGIC: &str = "xi undo 2";
+
+/// the full path of filename, which is what undo files are keyed by
+fn key(filename: &str) -> String {
+	fs::canonicalize(filename)
+		.unwrap_or_else(|_| PathBuf::from(filename))
+		.to_string_lossy()
+		.into_owned()
+}
+
+/// undo file used for filename, named after the hash of its full path
+pub fn undo_path(dir: &Path, filename: &str) -> PathBuf {
+	dir.join(format!("{:016x}.xiundo", file::hash(key(filename).as_bytes())))
+}
+
+/// saves history for filename, hash is the content hash of the written file
+pub fn write(dir: &Path, filename: &str, hash: u64, history: &History) -> io::Result<()> {
+	fs::create_dir_all(dir)?;
+	file::write_atomic(&undo_path(dir, filename), false, |wr| {
+		write!(wr, "{}\nhash {:016x}\nfile {}\n\n", MAGIC, hash, key(filename))?;
+		history.write_to(wr)
+	})
+}
+
+/// reads the history of filename, as long as it was saved for the same content
+pub fn read(dir: &Path, filename: &str, hash: u64) -> io::Result<History> {
+	let mut rd = io::BufReader::new(fs::File::open(undo_path(dir, filename))?);
+	let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+
+	let mut line = String::new();
+	rd.read_line(&mut line)?;
+	if line.trim_end() != MAGIC { return Err(bad("not an undo file")); }
+	loop {
+		line.clear();
+		rd.read_line(&mut line)?;
+		let header = line.trim_end_matches('\n');
+		if header.is_empty() { break; }
+		match header.split_once(' ') {
+			Some(("hash", v)) if u64::from_str_radix(v, 16) == Ok(hash) => {},
+			Some(("hash", _)) => return Err(bad("file changed since the undo file was written")),
+			Some(("file", v)) if v == key(filename) => {},
+			Some(("file", _)) => return Err(bad("undo file is for another file")),
+			_ => return Err(bad("not an undo file")),
+		}
+	}
+	History::read_from(&mut rd)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::scratch::Scratch;
+	use crate::history::Change;
+
+	#[test]
+	fn round_trip_test() {
+		let dir = Scratch::new("undo");
+		let filename = dir.join("f.txt").to_string_lossy().into_owned();
+		let mut h = History::default();
+		h.record(Change::insert(0, "hi"), 0, 2);
+		h.save();
+
+		write(&dir, &filename, 42, &h).unwrap();
+		let mut r = read(&dir, &filename, 42).unwrap();
+		let mut rope = ropey::Rope::from("hi");
+		assert_eq!(Some(0), r.undo(&mut rope).map(|l| l.cursor.cs));
+		assert_eq!("", rope.to_string());
+		assert!(read(&dir, &filename, 43).is_err(), "content changed");
+		assert!(read(&dir, &dir.join("g.txt").to_string_lossy(), 42).is_err());
+	}
+}
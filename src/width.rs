@@ -0,0 +1,258 @@
+/*
+* display widths - the cursor moves over grapheme clusters (a letter with its
+* accents, a whole emoji) and every cluster takes as many columns as the
+* terminal gives it: 2 for CJK and emoji, 0 for stray combining marks, tabs up
+* to the next tab stop.
+*
+* everything is counted in chars, like the rope is.
+*/
+use ropey::{Rope, RopeSlice};
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete, UnicodeSegmentation};
+use unicode_width::UnicodeWidthStr;
+
+/// a grapheme cluster on screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+	pub chars: usize,
+	pub width: usize,
+	/// a tab, its width depends on the column
+	pub tab: bool,
+	/// a space or a tab
+	pub blank: bool,
+}
+
+impl Cell {
+	/// columns taken by the cell at column col, without looking at the text again
+	pub fn width_at(&self, col: usize, tab_width: usize) -> usize {
+		if self.tab { tab_width - (col % tab_width) } else { self.width }
+	}
+}
+
+/// columns taken by grapheme g at column col
+pub fn width(g: &str, col: usize, tab_width: usize) -> usize {
+	match g {
+		"\t" => tab_width - (col % tab_width),
+		// the cursor sits on the line break
+		"\n" | "\r\n" => 1,
+		_ => g.width(),
+	}
+}
+
+/// the cells of text, which starts at column col
+pub fn cells(text: RopeSlice<'_>, mut col: usize, tab_width: usize) -> Vec<Cell> {
+	let text = text.to_string();
+	text.graphemes(true)
+		.map(|g| {
+			let cell = Cell {
+				chars: g.chars().count(),
+				width: width(g, col, tab_width),
+				tab: g == "\t",
+				blank: g == "\t" || g == " ",
+			};
+			col += cell.width;
+			cell
+		})
+		.collect()
+}
+
+/// columns taken by text, which starts at column col
+pub fn str_width(text: RopeSlice<'_>, col: usize, tab_width: usize) -> usize {
+	cells(text, col, tab_width).iter().map(|c| c.width).sum()
+}
+
+/// shown for control chars without list, the terminal would act on them
+pub const CTRL: char = '?';
+
+/// glyphs for whitespace and other chars that dont show, `:set listchars`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListChars {
+	/// first char of a tab, the one filling the rest and, if any, the last one
+	pub tab: Option<(char, char, Option<char>)>,
+	/// spaces at the end of a line
+	pub trail: Option<char>,
+	pub nbsp: Option<char>,
+	pub eol: Option<char>,
+	pub ctrl: char,
+}
+
+impl ListChars {
+	pub const DEFAULT: &str = "tab:> ,trail:-,nbsp:+,eol:$,ctrl:?";
+
+	/// reads `tab:>-,trail:~`, the glyphs left out arent shown
+	pub fn parse(s: &str) -> Result<ListChars, String> {
+		let mut list = ListChars { tab: None, trail: None, nbsp: None, eol: None, ctrl: CTRL };
+		for item in s.split(',').filter(|i| !i.is_empty()) {
+			let (name, value) = item.split_once(':').ok_or(format!("expected name:glyph, got {}", item))?;
+			let glyphs: Vec<char> = value.chars().collect();
+			// every glyph takes a single column, like what it stands for
+			if glyphs.iter().any(|c| c.to_string().width() != 1) {
+				return Err(format!("glyphs have to be one column wide: {}", item));
+			}
+			match (name, glyphs.as_slice()) {
+				("tab", &[first, fill]) => list.tab = Some((first, fill, None)),
+				("tab", &[first, fill, last]) => list.tab = Some((first, fill, Some(last))),
+				("trail", &[c]) => list.trail = Some(c),
+				("nbsp", &[c]) => list.nbsp = Some(c),
+				("eol", &[c]) => list.eol = Some(c),
+				("ctrl", &[c]) => list.ctrl = c,
+				("tab" | "trail" | "nbsp" | "eol" | "ctrl", _) => return Err(format!("wrong number of glyphs: {}", item)),
+				_ => return Err(format!("unknown listchars item: {}", name)),
+			}
+		}
+		Ok(list)
+	}
+}
+
+impl Default for ListChars {
+	fn default() -> Self {
+		ListChars::parse(ListChars::DEFAULT).expect("the default listchars are broken")
+	}
+}
+
+/// text as it goes on screen, tabs turned into spaces. with list, whitespace
+/// gets its glyphs (spaces only if trailing). a glyph takes just as many
+/// columns as what it stands for, so the cursor math doesnt change
+pub fn expand_tabs(text: RopeSlice<'_>, mut col: usize, tab_width: usize, list: Option<&ListChars>, trailing: bool) -> String {
+	let text = text.to_string();
+	let mut out = String::with_capacity(text.len());
+	for g in text.graphemes(true) {
+		let w = width(g, col, tab_width);
+		match (g, list) {
+			("\t", Some(ListChars { tab: Some((first, fill, last)), .. })) => {
+				let last = if w > 1 { Some(last.unwrap_or(*fill)) } else { None };
+				out.push(if w == 1 { last.unwrap_or(*first) } else { *first });
+				out.extend(std::iter::repeat_n(*fill, w.saturating_sub(2)));
+				out.extend(last);
+			},
+			("\t", _) => out.extend(std::iter::repeat_n(' ', w)),
+			("\n" | "\r\n", Some(ListChars { eol: Some(eol), .. })) => out.push(*eol),
+			("\n" | "\r\n", _) => out.push_str(g),
+			(" ", Some(ListChars { trail: Some(trail), .. })) if trailing => out.push(*trail),
+			("\u{a0}" | "\u{202f}", Some(ListChars { nbsp: Some(nbsp), .. })) => out.push(*nbsp),
+			_ if g.contains(char::is_control) => {
+				out.extend(std::iter::repeat_n(list.map_or(CTRL, |l| l.ctrl), w));
+			},
+			_ => out.push_str(g),
+		}
+		col += w;
+	}
+	out
+}
+
+/// text less its first cols columns, a wide grapheme cut in half leaves spaces
+pub fn skip_cols(text: &str, cols: usize) -> String {
+	let mut out = String::with_capacity(text.len());
+	let mut col = 0;
+	for g in text.graphemes(true) {
+		let w = g.width();
+		if col >= cols {
+			out.push_str(g);
+		} else if col + w > cols {
+			out.extend(std::iter::repeat_n(' ', col + w - cols));
+		}
+		col += w;
+	}
+	out
+}
+
+/// start of the grapheme after the one at cs
+pub fn next_grapheme(rope: &Rope, cs: usize) -> usize {
+	if cs >= rope.len_chars() { return rope.len_chars(); }
+	grapheme_boundary(rope, cs, true)
+}
+
+/// start of the grapheme before cs
+pub fn prev_grapheme(rope: &Rope, cs: usize) -> usize {
+	if cs == 0 { return 0; }
+	grapheme_boundary(rope, cs, false)
+}
+
+/// the closest grapheme boundary after (or before) cs. only the chunks of
+/// the rope around cs get looked at, not the whole line
+fn grapheme_boundary(rope: &Rope, cs: usize, forward: bool) -> usize {
+	let byte = rope.char_to_byte(cs);
+	let (mut chunk, mut chunk_byte, mut chunk_char, _) = rope.chunk_at_byte(byte);
+	let mut cursor = GraphemeCursor::new(byte, rope.len_bytes(), true);
+	loop {
+		let res = match forward {
+			true => cursor.next_boundary(chunk, chunk_byte),
+			false => cursor.prev_boundary(chunk, chunk_byte),
+		};
+		match res {
+			Ok(Some(b)) => return chunk_char + chunk[..b - chunk_byte].chars().count(),
+			Ok(None) => return if forward { rope.len_chars() } else { 0 },
+			Err(GraphemeIncomplete::NextChunk) => {
+				(chunk, chunk_byte, chunk_char, _) = rope.chunk_at_byte(chunk_byte + chunk.len());
+			},
+			Err(GraphemeIncomplete::PrevChunk) => {
+				(chunk, chunk_byte, chunk_char, _) = rope.chunk_at_byte(chunk_byte - 1);
+			},
+			Err(GraphemeIncomplete::PreContext(b)) => {
+				let (ctx, ctx_byte, _, _) = rope.chunk_at_byte(b - 1);
+				cursor.provide_context(ctx, ctx_byte);
+			},
+			// the cursor is always put on the rope it walks
+			Err(GraphemeIncomplete::InvalidOffset) => unreachable!(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cells_test() {
+		let rope = Rope::from("a\t日本e\u{301}👩‍🔬\n");
+		let widths: Vec<_> = cells(rope.slice(..), 0, 4).iter().map(|c| (c.chars, c.width)).collect();
+		assert_eq!(vec![(1, 1), (1, 3), (1, 2), (1, 2), (2, 1), (3, 2), (1, 1)], widths);
+		assert_eq!(4, str_width(rope.slice(2..4), 0, 4));
+		assert_eq!("a   日本", expand_tabs(rope.slice(..4), 0, 4, None, false));
+		assert_eq!("  x", expand_tabs(Rope::from("\tx").slice(..), 2, 4, None, false));
+		assert_eq!("本", skip_cols("a日本", 3));
+		assert_eq!(" 本", skip_cols("a日本", 2), "half of a wide char");
+		assert_eq!("", skip_cols("ab", 5));
+	}
+
+	#[test]
+	fn list_test() {
+		let list = ListChars::default();
+		let rope = Rope::from("a\tb\u{a0}\u{1}c  \n");
+		assert_eq!("a>  b+?c  $", expand_tabs(rope.slice(..), 0, 4, Some(&list), false));
+		assert_eq!("--$", expand_tabs(rope.slice(6..), 0, 4, Some(&list), true));
+		assert_eq!("a   b\u{a0}?c  \n", expand_tabs(rope.slice(..), 0, 4, None, false));
+
+		let list = ListChars::parse("tab:<->").unwrap();
+		assert_eq!("<-->|<->", expand_tabs(Rope::from("\t|\t").slice(..), 0, 4, Some(&list), false), "last at the tab stop");
+		assert_eq!("ab\n", expand_tabs(Rope::from("ab\n").slice(..), 0, 4, Some(&list), false));
+
+		assert!(ListChars::parse("tab:>").is_err());
+		assert!(ListChars::parse("eol:日").is_err());
+		assert!(ListChars::parse("space:.").is_err());
+		assert!(ListChars::parse("eol").is_err());
+	}
+
+	#[test]
+	fn grapheme_test() {
+		let rope = Rope::from("e\u{301}x\n👩‍🔬");
+		assert_eq!(2, next_grapheme(&rope, 0));
+		assert_eq!(2, next_grapheme(&rope, 1), "from the middle of a cluster");
+		assert_eq!(4, next_grapheme(&rope, 3), "over the line break");
+		assert_eq!(7, next_grapheme(&rope, 4));
+		assert_eq!(7, next_grapheme(&rope, 7));
+		assert_eq!(4, prev_grapheme(&rope, 7));
+		assert_eq!(3, prev_grapheme(&rope, 4));
+		assert_eq!(0, prev_grapheme(&rope, 2));
+		assert_eq!(0, prev_grapheme(&rope, 0));
+
+		// a rope big enough to have many chunks, clusters end up split across them
+		let text = "ab e\u{301}\u{302} 👩‍🔬\r\n日".repeat(2000);
+		let rope = Rope::from(text.as_str());
+		let mut starts = vec![0];
+		starts.extend(text.graphemes(true).scan(0, |cs, g| { *cs += g.chars().count(); Some(*cs) }));
+		for pair in starts.windows(2) {
+			assert_eq!(pair[1], next_grapheme(&rope, pair[0]));
+			assert_eq!(pair[0], prev_grapheme(&rope, pair[1]));
+		}
+	}
+}
@@ -0,0 +1,419 @@
+/*
+* windows - the screen is split into windows, each one looking at a buffer
+* from its own view (cursor, scroll, selection). more than one window can
+* show the same buffer.
+*
+* the layout is a tree of splits with windows as leaves. windows are
+* numbered by where they are in Editor::windows.
+*/
+use crate::buffer::View;
+use crate::Editor;
+use ratatui::layout::Rect;
+
+/// smallest window that still shows some text, border included
+const MIN_WIDTH: u16 = 8;
+const MIN_HEIGHT: u16 = 3;
+
+pub struct Window {
+	pub buf: usize,
+	/// where the window looks at its buffer from. None for the active
+	/// window, its view is in the buffer itself
+	pub view: Option<View>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Split {
+	/// one above the other, `split`
+	Horizontal,
+	/// side by side, `vsplit`
+	Vertical,
+}
+
+/// ways to go from a window to the next one over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+	Left,
+	Down,
+	Up,
+	Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layout {
+	Window(usize),
+	/// ratio is how much of the room goes to first
+	Split { split: Split, ratio: f32, first: Box<Layout>, second: Box<Layout> },
+}
+
+impl Default for Layout {
+	fn default() -> Self {
+		Layout::Window(0)
+	}
+}
+
+impl Layout {
+	/// every window with the part of area it gets
+	pub fn areas(&self, area: Rect) -> Vec<(usize, Rect)> {
+		let mut out = vec![];
+		self.collect(area, &mut out);
+		out
+	}
+
+	fn collect(&self, area: Rect, out: &mut Vec<(usize, Rect)>) {
+		match self {
+			Layout::Window(w) => out.push((*w, area)),
+			Layout::Split { split, ratio, first, second } => {
+				let (a, b) = divide(area, *split, *ratio);
+				first.collect(a, out);
+				second.collect(b, out);
+			},
+		}
+	}
+
+	/// puts window new next to win, before it
+	pub fn split(&mut self, win: usize, new: usize, split: Split) {
+		match self {
+			Layout::Window(w) if *w == win => *self = Layout::Split {
+				split,
+				ratio: 0.5,
+				first: Box::new(Layout::Window(new)),
+				second: Box::new(Layout::Window(win)),
+			},
+			Layout::Window(_) => {},
+			Layout::Split { first, second, .. } => {
+				first.split(win, new, split);
+				second.split(win, new, split);
+			},
+		}
+	}
+
+	/// takes win out, the window next to it gets its room.
+	/// windows after it move down a number
+	pub fn remove(&mut self, win: usize) {
+		self.cut(win);
+		self.renumber(win);
+	}
+
+	fn cut(&mut self, win: usize) {
+		let Layout::Split { first, second, .. } = self else { return; };
+		if **first == Layout::Window(win) {
+			*self = std::mem::take(second);
+		} else if **second == Layout::Window(win) {
+			*self = std::mem::take(first);
+		} else {
+			first.cut(win);
+			second.cut(win);
+		}
+	}
+
+	fn renumber(&mut self, removed: usize) {
+		match self {
+			Layout::Window(w) if *w > removed => *w -= 1,
+			Layout::Window(_) => {},
+			Layout::Split { first, second, .. } => {
+				first.renumber(removed);
+				second.renumber(removed);
+			},
+		}
+	}
+
+	fn contains(&self, win: usize) -> bool {
+		match self {
+			Layout::Window(w) => *w == win,
+			Layout::Split { first, second, .. } => first.contains(win) || second.contains(win),
+		}
+	}
+
+	/// grows win by amt rows (or columns, for Vertical), taken from the
+	/// windows next to it. false if there's no split that way
+	pub fn resize(&mut self, win: usize, area: Rect, split: Split, amt: i32) -> bool {
+		let Layout::Split { split: s, ratio, first, second } = self else { return false; };
+		let (a, b) = divide(area, *s, *ratio);
+		// the innermost split that way gets it
+		if first.contains(win) && first.resize(win, a, split, amt) { return true; }
+		if second.contains(win) && second.resize(win, b, split, amt) { return true; }
+		if *s != split { return false; }
+
+		let (size, total) = match split {
+			Split::Horizontal => (a.height as i32, area.height as i32),
+			Split::Vertical => (a.width as i32, area.width as i32),
+		};
+		let size = if first.contains(win) { size + amt } else { size - amt };
+		*ratio = size as f32 / total.max(1) as f32;
+		true
+	}
+
+	/// every split back to halves
+	pub fn equalize(&mut self) {
+		if let Layout::Split { ratio, first, second, .. } = self {
+			*ratio = 0.5;
+			first.equalize();
+			second.equalize();
+		}
+	}
+}
+
+/// the two parts of area, first gets ratio of it but both get at least a cell
+fn divide(area: Rect, split: Split, ratio: f32) -> (Rect, Rect) {
+	let total = match split {
+		Split::Horizontal => area.height,
+		Split::Vertical => area.width,
+	};
+	let size = match total {
+		0 | 1 => total,
+		_ => ((total as f32 * ratio).round() as u16).clamp(1, total - 1),
+	};
+	match split {
+		Split::Horizontal => (
+			Rect { height: size, ..area },
+			Rect { y: area.y + size, height: area.height - size, ..area },
+		),
+		Split::Vertical => (
+			Rect { width: size, ..area },
+			Rect { x: area.x + size, width: area.width - size, ..area },
+		),
+	}
+}
+
+/// the window next to win on side, the one closest to its top left corner
+pub fn neighbor(areas: &[(usize, Rect)], win: usize, side: Side) -> Option<usize> {
+	let (_, from) = *areas.iter().find(|(w, _)| *w == win)?;
+	areas.iter()
+		.filter(|(_, r)| match side {
+			Side::Left => r.right() == from.x && overlap(r.y, r.bottom(), from.y, from.bottom()),
+			Side::Right => r.x == from.right() && overlap(r.y, r.bottom(), from.y, from.bottom()),
+			Side::Up => r.bottom() == from.y && overlap(r.x, r.right(), from.x, from.right()),
+			Side::Down => r.y == from.bottom() && overlap(r.x, r.right(), from.x, from.right()),
+		})
+		.min_by_key(|(_, r)| r.x.abs_diff(from.x) + r.y.abs_diff(from.y))
+		.map(|(w, _)| *w)
+}
+
+fn overlap(a_start: u16, a_end: u16, b_start: u16, b_end: u16) -> bool {
+	a_start < b_end && b_start < a_end
+}
+
+impl Editor {
+	/// the room windows share, the screen less the tab bar and the status line
+	pub fn windows_area(&self) -> Rect {
+		let (w, h) = crossterm::terminal::size().unwrap_or((80, 24));
+		let top = self.tabline();
+		Rect { x: 0, y: top, width: w, height: h.saturating_sub(self.status + top) }
+	}
+
+	/// shows buffer i in the active window
+	pub fn show_buf(&mut self, i: usize) {
+		self.active_buf = i;
+		self.windows[self.active_win].buf = i;
+	}
+
+	/// makes w the active window
+	pub fn focus(&mut self, w: usize) {
+		if w == self.active_win || w >= self.windows.len() { return; }
+		self.stash_view();
+		self.active_win = w;
+		self.load_view();
+	}
+
+	/// the active window keeps a copy of its view, before it stops being active
+	pub fn stash_view(&mut self) {
+		self.sync_views();
+		self.windows[self.active_win].view = Some(self.active_buf().view());
+	}
+
+	/// the view of the active window goes into its buffer
+	pub fn load_view(&mut self) {
+		self.active_buf = self.windows[self.active_win].buf;
+		if let Some(mut view) = self.windows[self.active_win].view.take() {
+			self.bufs[self.active_buf].swap_view(&mut view);
+		}
+	}
+
+	/// runs f with the view of window w in its buffer
+	pub fn in_window<T>(&mut self, w: usize, f: impl FnOnce(&mut Editor, usize) -> T) -> T {
+		let b = self.windows[w].buf;
+		self.sync_views();
+		let Some(mut view) = self.windows[w].view.take() else { return f(self, b); };
+		self.bufs[b].swap_view(&mut view);
+		let res = f(self, b);
+		self.bufs[b].swap_view(&mut view);
+		// edits made by f are for the others to follow, not w
+		self.sync_views();
+		self.windows[w].view = Some(view);
+		res
+	}
+
+	/// the views of windows that arent active follow the edits made to their buffer,
+	/// done before any view goes into a buffer
+	fn sync_views(&mut self) {
+		for b in 0..self.bufs.len() {
+			let shifts = self.bufs[b].take_shifts();
+			if shifts.is_empty() { continue; }
+			for view in self.all_windows_mut().filter(|w| w.buf == b).filter_map(|w| w.view.as_mut()) {
+				view.shift(&shifts);
+			}
+		}
+	}
+
+	/// splits the active window in two, the new one shows the same buffer and gets active
+	pub fn split(&mut self, split: Split) -> Result<(), String> {
+		let new = self.windows.len();
+		let mut layout = self.layout.clone();
+		layout.split(self.active_win, new, split);
+		let small = layout.areas(self.windows_area()).iter()
+			.any(|(_, r)| r.width < MIN_WIDTH || r.height < MIN_HEIGHT);
+		if small { return Err("not enough room".to_owned()); }
+
+		self.layout = layout;
+		self.windows.push(Window { buf: self.active_buf, view: Option::None });
+		// the new window starts where this one is
+		self.stash_view();
+		self.active_win = new;
+		Ok(())
+	}
+
+	/// closes window w, the last one cant go
+	pub fn close(&mut self, w: usize) -> Result<(), String> {
+		if self.windows.len() == 1 { return Err("cant close the last window".to_owned()); }
+		let areas = self.layout.areas(self.windows_area());
+		// the window that takes its room gets active, like the one on its left or above
+		let next = [Side::Left, Side::Up, Side::Right, Side::Down].into_iter()
+			.find_map(|side| neighbor(&areas, w, side))
+			.unwrap_or(if w == 0 { 1 } else { 0 });
+		if w == self.active_win { self.focus(next); }
+
+		self.windows.remove(w);
+		self.layout.remove(w);
+		if self.active_win > w { self.active_win -= 1; }
+		Ok(())
+	}
+
+	/// closes every window but the active one
+	pub fn only(&mut self) {
+		let win = self.windows.remove(self.active_win);
+		self.windows = vec![win];
+		self.active_win = 0;
+		self.layout = Layout::Window(0);
+	}
+
+	/// what ctrl+w followed by key does
+	pub fn window_key(&mut self, key: crossterm::event::KeyCode) -> Result<(), String> {
+		use crossterm::event::KeyCode;
+		let (areas, win) = (self.layout.areas(self.windows_area()), self.active_win);
+		let go = |side| match neighbor(&areas, win, side) {
+			Some(w) => Ok(w),
+			Option::None => Err("no window there".to_owned()),
+		};
+		match key {
+			KeyCode::Char('h') | KeyCode::Left => self.focus(go(Side::Left)?),
+			KeyCode::Char('j') | KeyCode::Down => self.focus(go(Side::Down)?),
+			KeyCode::Char('k') | KeyCode::Up => self.focus(go(Side::Up)?),
+			KeyCode::Char('l') | KeyCode::Right => self.focus(go(Side::Right)?),
+			KeyCode::Char('w') => self.focus((self.active_win + 1) % self.windows.len()),
+			KeyCode::Char('s') => self.split(Split::Horizontal)?,
+			KeyCode::Char('v') => self.split(Split::Vertical)?,
+			KeyCode::Char('c') | KeyCode::Char('q') => self.close(self.active_win)?,
+			KeyCode::Char('o') => self.only(),
+			KeyCode::Char('+') => self.resize_window(Split::Horizontal, 1)?,
+			KeyCode::Char('-') => self.resize_window(Split::Horizontal, -1)?,
+			KeyCode::Char('>') => self.resize_window(Split::Vertical, 1)?,
+			KeyCode::Char('<') => self.resize_window(Split::Vertical, -1)?,
+			KeyCode::Char('=') => self.layout.equalize(),
+			_ => {},
+		}
+		Ok(())
+	}
+
+	/// grows the active window, unless another one would get too small
+	fn resize_window(&mut self, split: Split, amt: i32) -> Result<(), String> {
+		let area = self.windows_area();
+		let mut layout = self.layout.clone();
+		if !layout.resize(self.active_win, area, split, amt) { return Ok(()); }
+		let small = layout.areas(area).iter()
+			.any(|(_, r)| r.width < MIN_WIDTH || r.height < MIN_HEIGHT);
+		if !small { self.layout = layout; }
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const AREA: Rect = Rect { x: 0, y: 0, width: 80, height: 20 };
+
+	#[test]
+	fn layout_test() {
+		let mut layout = Layout::default();
+		layout.split(0, 1, Split::Horizontal);
+		layout.split(0, 2, Split::Vertical);
+		assert_eq!(vec![
+			(1, Rect::new(0, 0, 80, 10)),
+			(2, Rect::new(0, 10, 40, 10)),
+			(0, Rect::new(40, 10, 40, 10)),
+		], layout.areas(AREA));
+
+		assert!(layout.resize(1, AREA, Split::Horizontal, 2));
+		assert_eq!(Rect::new(0, 0, 80, 12), layout.areas(AREA)[0].1);
+		assert!(layout.resize(0, AREA, Split::Vertical, 10));
+		assert_eq!(Rect::new(30, 12, 50, 8), layout.areas(AREA)[2].1);
+		assert!(!layout.resize(1, AREA, Split::Vertical, 1), "nothing next to it");
+		layout.equalize();
+		assert_eq!(Rect::new(0, 0, 80, 10), layout.areas(AREA)[0].1);
+
+		layout.remove(1);
+		assert_eq!(vec![(1, Rect::new(0, 0, 40, 20)), (0, Rect::new(40, 0, 40, 20))], layout.areas(AREA));
+		layout.remove(0);
+		assert_eq!(Layout::Window(0), layout);
+	}
+
+	#[test]
+	fn views_test() {
+		use crate::buffer::{Buffer, Direction, Move};
+		let mut ed = Editor::default();
+		ed.bufs.push(Buffer::open("a".to_owned(), ropey::Rope::from("abc\ndef\nghi"), 20, 5));
+		ed.split(Split::Horizontal).unwrap();
+		assert_eq!((2, 1), (ed.windows.len(), ed.active_win));
+		ed.active_buf_mut().cursor_mv(Move::Exact(Direction::Vert, 2));
+		ed.active_buf_mut().insert('x');
+
+		// the other window has its own cursor but sees the edit
+		ed.focus(0);
+		assert_eq!(0, ed.active_buf().cs());
+		ed.active_buf_mut().cursor_end();
+		assert_eq!(3, ed.active_buf().cs());
+		ed.focus(1);
+		assert_eq!(9, ed.active_buf().cs());
+		assert_eq!("abc\ndef\nxghi", ed.in_window(0, |ed, b| ed.bufs[b].lines.to_string()));
+
+		// an edit above moves the cursor of the other window along with its text
+		ed.active_buf_mut().cursor_mv(Move::Exact(Direction::Vert, -2));
+		ed.active_buf_mut().insert('y');
+		ed.active_buf_mut().insert('\n');
+		ed.focus(0);
+		assert_eq!(5, ed.active_buf().cs());
+		assert_eq!('\n', ed.active_buf().lines.char(ed.active_buf().cs()));
+		ed.focus(1);
+		ed.active_buf_mut().delete(3, false);
+		ed.focus(0);
+		assert_eq!(3, ed.active_buf().cs(), "deleted text leaves it where the edit was");
+
+		ed.close(1).unwrap();
+		assert_eq!((1, 0, 3), (ed.windows.len(), ed.active_win, ed.active_buf().cs()));
+		assert!(ed.close(0).is_err());
+	}
+
+	#[test]
+	fn neighbor_test() {
+		let mut layout = Layout::default();
+		layout.split(0, 1, Split::Vertical);
+		layout.split(0, 2, Split::Horizontal);
+		// 1 on the left, 2 above 0 on the right
+		let areas = layout.areas(AREA);
+		assert_eq!(Some(2), neighbor(&areas, 1, Side::Right), "the top one");
+		assert_eq!(Some(1), neighbor(&areas, 0, Side::Left));
+		assert_eq!(Some(0), neighbor(&areas, 2, Side::Down));
+		assert_eq!(Some(2), neighbor(&areas, 0, Side::Up));
+		assert_eq!(None, neighbor(&areas, 1, Side::Up));
+	}
+}